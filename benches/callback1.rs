@@ -0,0 +1,63 @@
+// Compares `run_all_callback1` with `run_all_callback1_parallel` on plugins that spend their time
+// waiting, as plugins doing I/O would. Run it with `cargo bench`. Rayon starts a thread per CPU,
+// so on a machine with few of them, set `RAYON_NUM_THREADS` to see the difference.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core::{Plugin, PluginConfig, PluginMetadata, PluginRegistrar};
+use plugin_example::{PluginManager, PluginManagerBuilder};
+
+const PLUGINS: usize = 50;
+const SLEEP: Duration = Duration::from_millis(10);
+const RUNS: u32 = 5;
+
+/// Sleeps in `callback1`, standing in for a plugin waiting on I/O.
+struct Sleepy;
+
+impl Plugin for Sleepy {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Sleepy",
+            version: env!("CARGO_PKG_VERSION"),
+            description: None,
+        }
+    }
+
+    fn callback1(&self) {
+        thread::sleep(SLEEP);
+    }
+}
+
+/// How long `run` takes on average.
+fn time(manager: &PluginManager, run: impl Fn(&PluginManager)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run(manager);
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let mut manager = PluginManagerBuilder::new().build();
+    for i in 0..PLUGINS {
+        manager.register_plugin_with_config(
+            Box::new(Sleepy),
+            PluginConfig {
+                instance_name: Some(format!("Sleepy{}", i)),
+                ..PluginConfig::default()
+            },
+        );
+    }
+
+    let sequential = time(&manager, |manager| {
+        manager.run_all_callback1();
+    });
+    let parallel = time(&manager, |manager| {
+        manager.run_all_callback1_parallel();
+    });
+    println!(
+        "{} plugins sleeping {:?} each: sequential {:.1?}, parallel {:.1?}",
+        PLUGINS, SLEEP, sequential, parallel
+    );
+}
@@ -1,16 +1,919 @@
+// The traits only need `alloc`, so embedded hosts can use them without `std`. The helpers that
+// need `std`, such as `call_safely`, are behind the `std` feature, which is on by default.
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+
+pub use async_trait::async_trait;
+
+// `async_trait` expands to paths like `::core::pin::Pin`, which name this crate rather than the
+// real `core` in the plugins that depend on it, so provide the modules it uses. `tokio::test`
+// does the same with `::core::prelude`.
+#[doc(hidden)]
+pub use core::{future, marker, option, pin, prelude};
+
+/// The version of the plugin ABI defined by this crate. Plugins export this value as
+/// `PLUGIN_ABI_VERSION` so the application can refuse libraries built against an incompatible
+/// version of these traits. Bump it whenever `Plugin`, `PluginRegistrar`, `HostContext`,
+/// `PluginError` or the signature of `plugin_entry` change.
+pub const ABI_VERSION: u32 = 30;
+
+/// Defines `PluginEntry` and `ENTRY_SIGNATURE` from the same tokens, so the signature that's
+/// hashed can't drift from the one plugins are compiled against.
+macro_rules! entry_signature {
+    ($($signature:tt)*) => {
+        /// The type of `plugin_entry`, the function the application calls to have a library
+        /// register its plugins. `export_plugin!` checks a plugin's entry function has it.
+        pub type PluginEntry = $($signature)*;
+
+        /// `PluginEntry`, spelled out.
+        pub const ENTRY_SIGNATURE: &str = stringify!($($signature)*);
+    };
+}
+
+entry_signature!(fn(&mut dyn PluginRegistrar, &HostContext));
+
+/// A hash of `ENTRY_SIGNATURE`. Plugins export this value as `PLUGIN_ENTRY_SIGNATURE` so the
+/// application can check it's about to call `plugin_entry` the way it was defined.
+pub const ENTRY_SIGNATURE_HASH: u64 = fnv1a(ENTRY_SIGNATURE.as_bytes());
+
+/// Exports the symbols the application looks for in a plugin library: a `plugin_entry` that
+/// calls `$entry`, and the `PLUGIN_ABI_VERSION`, `RUSTC_VERSION` and `PLUGIN_ENTRY_SIGNATURE`
+/// statics it checks before calling it. A plugin whose `$entry` isn't a `PluginEntry` doesn't
+/// compile, so the signature it exports is always the one it was built with.
+///
+/// ```ignore
+/// pub fn register(registrar: &mut dyn core::PluginRegistrar, ctx: &core::HostContext) {
+///     registrar.register_plugin(Box::new(PluginA));
+/// }
+///
+/// core::export_plugin!(register);
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($entry:path) => {
+        #[no_mangle]
+        pub static PLUGIN_ABI_VERSION: u32 = $crate::ABI_VERSION;
+
+        #[no_mangle]
+        pub static RUSTC_VERSION: &str = $crate::RUSTC_VERSION;
+
+        #[no_mangle]
+        pub static PLUGIN_ENTRY_SIGNATURE: u64 = $crate::ENTRY_SIGNATURE_HASH;
+
+        #[no_mangle]
+        pub fn plugin_entry(
+            registrar: &mut dyn $crate::PluginRegistrar,
+            ctx: &$crate::HostContext,
+        ) {
+            const ENTRY: $crate::PluginEntry = $entry;
+            ENTRY(registrar, ctx)
+        }
+
+        const _: $crate::PluginEntry = plugin_entry;
+    };
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which is simple enough to compute at compile time.
+const fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+/// The output of `rustc --version` for the compiler that built this crate. Plugins export this
+/// value as `RUSTC_VERSION`: the layout of trait objects isn't stable between compilers, so a
+/// plugin built with a different one may misbehave even if its ABI version matches.
+pub const RUSTC_VERSION: &str = env!("CORE_RUSTC_VERSION");
+
+/// Whether a plugin that exported `plugin` as its `RUSTC_VERSION` was built with the same
+/// compiler as the application.
+pub fn rustc_version_matches(plugin: &str) -> bool {
+    plugin == RUSTC_VERSION
+}
+
 /// The `PluginRegistrar` is defined by the application and passed to `plugin_entry`. It's used
 /// for a plugin module to register itself with the application.
 pub trait PluginRegistrar {
-    fn register_plugin(&mut self, plugin: Box<dyn Plugin>);
+    /// Registers `plugin` with the default `PluginConfig`, returning the id it was registered
+    /// under.
+    fn register_plugin(&mut self, plugin: Box<dyn Plugin>) -> PluginId {
+        self.register_plugin_with_config(plugin, PluginConfig::default())
+    }
+
+    /// Registers `plugin`, letting the caller disable it or register it under a different name.
+    /// Returns the id it was registered under.
+    fn register_plugin_with_config(
+        &mut self,
+        plugin: Box<dyn Plugin>,
+        config: PluginConfig,
+    ) -> PluginId;
+
+    /// Registers several plugins at once, in order. Useful when a single library provides more
+    /// than one plugin.
+    fn register_plugins(&mut self, plugins: Vec<Box<dyn Plugin>>) {
+        for plugin in plugins {
+            self.register_plugin(plugin);
+        }
+    }
+
+    /// Registers a `Formatter`. A library may register formatters as well as, or instead of,
+    /// plugins. By default formatters are dropped, for registrars with no use for them.
+    fn register_formatter(&mut self, formatter: Box<dyn Formatter>) {
+        drop(formatter);
+    }
+
+    /// Registers an `AsyncPlugin`. Async plugins are kept apart from the `Plugin`s a library
+    /// registers, and are only called by the application's async code. By default they're
+    /// dropped, for registrars with no async code to call them.
+    fn register_async_plugin(&mut self, plugin: Box<dyn AsyncPlugin>) {
+        drop(plugin);
+    }
+}
+
+impl dyn PluginRegistrar + '_ {
+    /// Registers a plugin named `name` made from a pair of closures, for plugins too simple to
+    /// need a type of their own. `callback2` can't fail.
+    pub fn register_fn(
+        &mut self,
+        name: &'static str,
+        callback1: impl Fn() + Send + Sync + 'static,
+        callback2: impl Fn(i32) -> i32 + Send + Sync + 'static,
+    ) -> PluginId {
+        self.register_plugin(Box::new(ClosurePlugin {
+            name,
+            callback1,
+            callback2,
+        }))
+    }
+}
+
+/// The plugin registered by `register_fn`.
+struct ClosurePlugin<F, G> {
+    name: &'static str,
+    callback1: F,
+    callback2: G,
+}
+
+impl<F, G> Plugin for ClosurePlugin<F, G>
+where
+    F: Fn() + Send + Sync + 'static,
+    G: Fn(i32) -> i32 + Send + Sync + 'static,
+{
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: self.name,
+            version: env!("CARGO_PKG_VERSION"),
+            description: None,
+        }
+    }
+
+    fn callback1(&self) {
+        (self.callback1)()
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        Ok((self.callback2)(i))
+    }
+}
+
+/// Identifies a plugin registered with the application. Ids are never reused, so an id keeps
+/// referring to the same plugin however many others are unloaded, and stops referring to
+/// anything once that plugin is unloaded. A plugin the application rejected still gets an id,
+/// but it never refers to anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PluginId(u64);
+
+impl PluginId {
+    /// Creates an id from its raw value. Only the application should make new ids.
+    pub fn new(id: u64) -> Self {
+        PluginId(id)
+    }
+
+    /// The raw value of this id.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// How a plugin is registered with the application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginConfig {
+    /// Disabled plugins are still loaded, but the application won't call their callbacks
+    pub enabled: bool,
+    /// The name the plugin is registered and looked up under, instead of the name in its
+    /// metadata. Lets several instances of the same plugin be registered side by side.
+    pub instance_name: Option<String>,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        PluginConfig {
+            enabled: true,
+            instance_name: None,
+        }
+    }
+}
+
+/// The file extension used for dynamic libraries on this platform, without the leading dot:
+/// `so` on Linux, `dylib` on macOS and `dll` on Windows.
+#[cfg(feature = "std")]
+pub const DYLIB_EXTENSION: &str = std::env::consts::DLL_EXTENSION;
+
+/// Returns the file name the platform uses for a dynamic library named `stem`, e.g.
+/// `libplugin_a.so`, `libplugin_a.dylib` or `plugin_a.dll`.
+#[cfg(feature = "std")]
+pub fn platform_lib_name(stem: &str) -> String {
+    format!(
+        "{}{}{}",
+        std::env::consts::DLL_PREFIX,
+        stem,
+        std::env::consts::DLL_SUFFIX
+    )
+}
+
+/// The severity of a message logged through a `HostLogger`, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// `HostLogger` is implemented by the application so plugins can report messages through the
+/// application instead of printing directly. The application decides which levels are shown.
+pub trait HostLogger: Send + Sync {
+    /// Logs `message` at `level`. `target` is the name of the plugin the message came from.
+    fn log(&self, level: LogLevel, target: &str, message: &str);
+}
+
+/// A handle for logging through the application, returned by `HostContext::log`.
+pub struct Log<'a> {
+    logger: &'a dyn HostLogger,
+    target: &'a str,
+}
+
+impl Log<'_> {
+    pub fn error(&self, message: &str) {
+        self.logger.log(LogLevel::Error, self.target, message);
+    }
+
+    pub fn warn(&self, message: &str) {
+        self.logger.log(LogLevel::Warn, self.target, message);
+    }
+
+    pub fn info(&self, message: &str) {
+        self.logger.log(LogLevel::Info, self.target, message);
+    }
+
+    pub fn debug(&self, message: &str) {
+        self.logger.log(LogLevel::Debug, self.target, message);
+    }
+
+    pub fn trace(&self, message: &str) {
+        self.logger.log(LogLevel::Trace, self.target, message);
+    }
+}
+
+/// `HostConfig` is a read-only key/value configuration map populated by the application.
+pub trait HostConfig: Send + Sync {
+    fn get(&self, key: &str) -> Option<&str>;
+}
+
+#[cfg(feature = "std")]
+impl HostConfig for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key).map(String::as_str)
+    }
+}
+
+/// A handler subscribed to an `EventBus` topic, called with each event's payload.
+pub type EventHandler = Box<dyn Fn(&[u8]) + Send + Sync>;
+
+/// `EventBus` lets plugins communicate without knowing about each other. Payloads are plain
+/// bytes to keep the boundary simple.
+pub trait EventBus: Send + Sync {
+    /// Calls every handler subscribed to `topic` with `payload`.
+    fn publish(&self, topic: &str, payload: &[u8]);
+    /// Calls `handler` whenever something is published to `topic`.
+    fn subscribe(&self, topic: &str, handler: EventHandler);
+}
+
+/// The event bus used until the application provides one. It drops everything.
+struct NoEventBus;
+
+impl EventBus for NoEventBus {
+    fn publish(&self, _topic: &str, _payload: &[u8]) {}
+
+    fn subscribe(&self, _topic: &str, _handler: EventHandler) {}
+}
+
+/// `ProgressSink` is implemented by the application to receive progress updates from plugins
+/// doing lengthy work, reported through `HostContext::report_progress`.
+pub trait ProgressSink: Send + Sync {
+    /// Reports that the plugin named `plugin` is `fraction` of the way through its work, from
+    /// `0.0` to `1.0`.
+    fn report(&self, plugin: &str, fraction: f32, message: &str);
+}
+
+/// The progress sink used until the application provides one. It drops every update.
+struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn report(&self, _plugin: &str, _fraction: f32, _message: &str) {}
+}
+
+/// `PluginStateStore` is a key/value store the application keeps for each plugin, which outlives
+/// the plugin itself. A plugin can save its state here in `on_unload` and read it back in
+/// `on_load` after it's reloaded. Values are plain bytes to keep the boundary simple.
+pub trait PluginStateStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn set(&self, key: &str, value: Vec<u8>);
+}
+
+/// The state store used until the application provides one. It forgets everything.
+struct NoState;
+
+impl PluginStateStore for NoState {
+    fn get(&self, _key: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn set(&self, _key: &str, _value: Vec<u8>) {}
+}
+
+/// A flag the application sets to ask a plugin to stop what it's doing. Plugins doing lengthy
+/// work should check `HostContext::is_cancelled` now and then, and return early once it's set.
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks whoever holds this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears the flag, so work can start again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// `HostContext` is created by the application and passed to `plugin_entry`, giving plugins
+/// access to services provided by the application. Services are exposed as trait objects so no
+/// generics cross the plugin boundary. It's cheap to clone, so plugins may hold on to it.
+#[derive(Clone)]
+pub struct HostContext {
+    logger: Arc<dyn HostLogger>,
+    config: Arc<dyn HostConfig>,
+    events: Arc<dyn EventBus>,
+    progress: Arc<dyn ProgressSink>,
+    state: Arc<dyn PluginStateStore>,
+    cancellation: CancellationToken,
+    plugin_name: Option<Arc<str>>,
+    args: Arc<[String]>,
+}
+
+impl HostContext {
+    pub fn new(logger: Arc<dyn HostLogger>, config: Arc<dyn HostConfig>) -> Self {
+        HostContext {
+            logger,
+            config,
+            events: Arc::new(NoEventBus),
+            progress: Arc::new(NoProgress),
+            state: Arc::new(NoState),
+            cancellation: CancellationToken::new(),
+            plugin_name: None,
+            args: Arc::new([]),
+        }
+    }
+
+    /// Scopes the context to the plugin named `name`, which is used as the target of its log
+    /// messages. The application does this before handing the context to `Plugin::on_load`.
+    pub fn with_plugin_name(mut self, name: &str) -> Self {
+        self.plugin_name = Some(name.into());
+        self
+    }
+
+    /// The name of the plugin this context is scoped to, if any
+    pub fn plugin_name(&self) -> Option<&str> {
+        self.plugin_name.as_deref()
+    }
+
+    /// Sets the command-line arguments meant for the plugins this context is handed to. The
+    /// application does this before loading each plugin library.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args.into();
+        self
+    }
+
+    /// The command-line arguments the application was given for this plugin, which may be empty
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Replaces the event bus, which otherwise drops every event.
+    pub fn with_events(mut self, events: Arc<dyn EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Replaces the progress sink, which otherwise drops every update.
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Replaces the state store, which otherwise forgets everything. The application does this
+    /// before handing the context to `Plugin::on_load`, with a store for that plugin.
+    pub fn with_state(mut self, state: Arc<dyn PluginStateStore>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// The state the application keeps for the plugin this context is scoped to
+    pub fn state(&self) -> &dyn PluginStateStore {
+        &*self.state
+    }
+
+    /// Replaces the cancellation token. The application does this before handing the context to
+    /// `Plugin::on_load`, with a token for that plugin.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Whether the application has asked the plugin this context is scoped to to stop what it's
+    /// doing.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Tells the application how far along the plugin is with its current work, from `0.0` to
+    /// `1.0`. Fractions outside that range are clamped.
+    pub fn report_progress(&self, fraction: f32, message: &str) {
+        self.progress.report(
+            self.plugin_name().unwrap_or("plugin"),
+            fraction.clamp(0.0, 1.0),
+            message,
+        );
+    }
+
+    /// Logs through the application, targeted at the plugin this context is scoped to, e.g.
+    /// `ctx.log().info("...")`.
+    pub fn log(&self) -> Log<'_> {
+        Log {
+            logger: &*self.logger,
+            target: self.plugin_name().unwrap_or("plugin"),
+        }
+    }
+
+    /// The configuration the application was started with
+    pub fn config(&self) -> &dyn HostConfig {
+        &*self.config
+    }
+
+    /// The bus plugins use to publish events to each other
+    pub fn events(&self) -> &dyn EventBus {
+        &*self.events
+    }
+
+    /// Looks up the configuration value for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.config.get(key)
+    }
+
+    /// Looks up the configuration value for `key` as an `i32`, returning `None` if it's missing
+    /// or doesn't parse.
+    pub fn get_i32(&self, key: &str) -> Option<i32> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Looks up the configuration value for `key` as a `bool` (`true` or `false`), returning
+    /// `None` if it's missing or doesn't parse.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key)?.parse().ok()
+    }
+}
+
+/// `PluginMetadata` describes a plugin to the application, so it can tell plugins apart once
+/// they've been loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginMetadata {
+    /// A short, unique name for the plugin
+    pub name: &'static str,
+    /// The version of the plugin, typically `env!("CARGO_PKG_VERSION")`
+    pub version: &'static str,
+    /// An optional human readable description of what the plugin does
+    pub description: Option<&'static str>,
+}
+
+/// How a plugin is doing, as reported by `Plugin::health`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// The plugin is working normally
+    Ok,
+    /// The plugin still works, but not as well as it should
+    Degraded(String),
+    /// The plugin has stopped working
+    Failed(String),
+}
+
+impl Health {
+    /// Whether this is `Health::Ok`.
+    pub fn is_ok(&self) -> bool {
+        *self == Health::Ok
+    }
+}
+
+impl core::fmt::Display for Health {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Health::Ok => write!(f, "ok"),
+            Health::Degraded(reason) => write!(f, "degraded: {}", reason),
+            Health::Failed(reason) => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+/// `Capabilities` advertises which callbacks a plugin actually handles, so the application can
+/// skip calling the others. Flags are combined with `|`, e.g.
+/// `Capabilities::HANDLES_CALLBACK1 | Capabilities::HANDLES_CALLBACK2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// The plugin handles no callbacks
+    pub const NONE: Capabilities = Capabilities(0);
+    /// The plugin handles `callback1`
+    pub const HANDLES_CALLBACK1: Capabilities = Capabilities(1 << 0);
+    /// The plugin handles `callback2`
+    pub const HANDLES_CALLBACK2: Capabilities = Capabilities(1 << 1);
+    /// The plugin handles every callback
+    pub const ALL: Capabilities = Capabilities((1 << 0) | (1 << 1));
+
+    /// Returns whether every flag set in `other` is also set in `self`.
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The raw flag bits
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Describes a value a callback takes or returns: its type, and the range it must fall in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueSchema {
+    /// The value's Rust type, e.g. `"i32"`
+    pub ty: &'static str,
+    /// The smallest value allowed, if any
+    pub min: Option<i64>,
+    /// The largest value allowed, if any
+    pub max: Option<i64>,
+}
+
+impl ValueSchema {
+    /// Any `i32`
+    pub const I32: ValueSchema = ValueSchema {
+        ty: "i32",
+        min: None,
+        max: None,
+    };
+
+    pub const fn with_min(mut self, min: i64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub const fn with_max(mut self, max: i64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Checks that `value` is in range, describing the range if it isn't.
+    pub fn check(&self, value: i64) -> Result<(), String> {
+        let too_small = self.min.is_some_and(|min| value < min);
+        let too_large = self.max.is_some_and(|max| value > max);
+        if too_small || too_large {
+            Err(format!("{} is not {}", value, self))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl core::fmt::Display for ValueSchema {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match (self.min, self.max) {
+            (None, None) => write!(f, "{}", self.ty),
+            (Some(min), None) => write!(f, "{} >= {}", self.ty, min),
+            (None, Some(max)) => write!(f, "{} <= {}", self.ty, max),
+            (Some(min), Some(max)) => write!(f, "{} in {}..={}", self.ty, min, max),
+        }
+    }
+}
+
+/// Describes what a plugin's callbacks take and return, so the application can check input
+/// before calling them, and tell its users what's expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackSchema {
+    /// What `callback2` takes
+    pub callback2_input: ValueSchema,
+    /// What `callback2` returns
+    pub callback2_output: ValueSchema,
+}
+
+impl Default for CallbackSchema {
+    fn default() -> Self {
+        CallbackSchema {
+            callback2_input: ValueSchema::I32,
+            callback2_output: ValueSchema::I32,
+        }
+    }
+}
+
+impl core::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, rhs: Capabilities) {
+        self.0 |= rhs.0;
+    }
 }
 
 /// `Plugin` is implemented by a plugin library for one or more types. As you need additional
 /// callbacks, they can be defined here. These are first class Rust trait objects, so you have the
 /// full flexibility of that system. The main thing you'll lose access to is generics, but that's
 /// expected with a plugin system
-pub trait Plugin {
+///
+/// Plugins must be `Send + Sync`, since the application may call into them from several threads
+/// at once. Keep any mutable state behind a `Mutex` or atomics; implementing `Plugin` for a type
+/// that isn't thread-safe is a compile error.
+pub trait Plugin: Send + Sync {
+    /// Describes the plugin to the application.
+    fn metadata(&self) -> PluginMetadata;
+    /// Called right after the plugin is registered. Returning an error causes the application
+    /// to drop the plugin instead of adding it to the active set.
+    fn on_load(&mut self, _ctx: &HostContext) -> Result<(), PluginError> {
+        Ok(())
+    }
+    /// Called before the plugin is dropped, while its library is still loaded.
+    fn on_unload(&mut self) {}
+    /// Plugins are run in ascending order of priority. Plugins with the same priority run in
+    /// the order they were registered.
+    fn priority(&self) -> i32 {
+        0
+    }
+    /// The names of other plugins this plugin needs. They're run before this plugin, and this
+    /// plugin is rejected if any of them aren't loaded.
+    fn dependencies(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// The callbacks this plugin handles. The application only calls the callbacks advertised
+    /// here, so a plugin can leave the others unimplemented.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::ALL
+    }
+    /// Describes what the plugin's callbacks take and return. The application won't call
+    /// `callback2` with input outside the range given here.
+    fn schema(&self) -> CallbackSchema {
+        CallbackSchema::default()
+    }
+    /// The group the plugin belongs to, if any, so the application can run related plugins
+    /// together.
+    fn group(&self) -> Option<&'static str> {
+        None
+    }
+    /// Reports whether the plugin is still working. Long-running applications poll this, so it
+    /// should be cheap.
+    fn health(&self) -> Health {
+        Health::Ok
+    }
     /// This is a callback routine implemented by the plugin.
-    fn callback1(&self);
-    /// Callbacks can take arguments and return values
-    fn callback2(&self, i: i32) -> i32;
+    fn callback1(&self) {}
+    /// Callbacks can take arguments and return values. Errors are reported as a `String` to keep
+    /// the boundary simple.
+    fn callback2(&self, _i: i32) -> Result<i32, String> {
+        Err(format!(
+            "{} does not handle callback2",
+            self.metadata().name
+        ))
+    }
+    /// The names of the commands the plugin provides, beyond its callbacks, for the application
+    /// to run with `run_command`.
+    fn commands(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// Runs the command called `name`, one of those listed by `commands`, with `args`. The
+    /// result is shown to the user as is.
+    fn run_command(&self, name: &str, _args: &[String]) -> Result<String, String> {
+        Err(format!(
+            "{} has no command named `{}`",
+            self.metadata().name,
+            name
+        ))
+    }
+}
+
+/// A `Formatter` is a different kind of plugin, which formats text for the application. Like
+/// `Plugin`, it must be thread-safe.
+pub trait Formatter: Send + Sync {
+    fn format(&self, input: &str) -> String;
+}
+
+/// `AsyncPlugin` is the counterpart to `Plugin` for plugins whose callbacks wait on I/O, for
+/// use in an async application. Implementations need the `async_trait` attribute re-exported
+/// by this crate, as in `#[core::async_trait] impl core::AsyncPlugin for MyPlugin { ... }`.
+#[async_trait]
+pub trait AsyncPlugin: Send + Sync {
+    /// Describes the plugin to the application.
+    fn metadata(&self) -> PluginMetadata;
+    /// Like `Plugin::callback1`.
+    async fn callback1(&self) {}
+    /// Like `Plugin::callback2`, but can't fail.
+    async fn callback2(&self, i: i32) -> i32;
+}
+
+/// Errors that can occur while the application loads a plugin library.
+#[derive(Debug)]
+pub enum PluginError {
+    /// A plugin directory could not be read. Like the other I/O errors, the underlying error is
+    /// kept as a message, so the layout of this type doesn't depend on the `std` feature.
+    ReadDir(String),
+    /// The library could not be opened
+    LibraryOpen(String),
+    /// The library does not export a `plugin_entry` symbol
+    MissingEntrypoint,
+    /// The library's `plugin_entry` has a different signature than the application expects, as
+    /// told by the `PLUGIN_ENTRY_SIGNATURE` it exports
+    EntrySignatureMismatch { expected: u64, found: u64 },
+    /// The library was built against an incompatible version of this crate. `found` is `None`
+    /// when the library doesn't export `PLUGIN_ABI_VERSION` at all.
+    AbiMismatch { expected: u32, found: Option<u32> },
+    /// The library's `plugin_entry` didn't register any plugins or formatters
+    NoPlugins,
+    /// A plugin with this name was already registered
+    DuplicateName(String),
+    /// A plugin depends on another plugin that isn't loaded
+    MissingDependency { plugin: String, dependency: String },
+    /// These plugins depend on each other in a cycle, or depend on plugins in a cycle
+    DependencyCycle(Vec<String>),
+    /// A plugin manifest could not be parsed
+    Manifest(String),
+    /// An I/O error raised by a plugin or while managing plugins
+    Io(String),
+    /// A plugin panicked while the application was calling into it
+    Panicked(String),
+    /// No plugin with this name is registered
+    NotFound(String),
+    /// The plugin with this name is registered, but disabled, or was disabled for failing too
+    /// often, so the application won't call it
+    Disabled(String),
+    /// The plugin with this name wasn't loaded from a library, so it can't be reloaded
+    NotReloadable(String),
+    /// A plugin callback returned an error
+    Callback(String),
+    /// A plugin callback didn't return within the time it was given
+    Timeout(Duration),
+    /// The input for a plugin callback is outside the range its `schema` allows
+    InvalidInput(String),
+    /// A plugin doesn't provide a command with this name
+    UnknownCommand { plugin: String, command: String },
+    /// A plugin's arithmetic overflowed, e.g. in `safe_add`
+    Overflow(String),
+}
+
+impl core::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PluginError::ReadDir(err) => write!(f, "could not read plugin directory: {}", err),
+            PluginError::LibraryOpen(err) => write!(f, "could not open library: {}", err),
+            PluginError::MissingEntrypoint => write!(f, "library does not export `plugin_entry`"),
+            PluginError::EntrySignatureMismatch { expected, found } => write!(
+                f,
+                "library's `plugin_entry` has signature hash {:#x}, expected {:#x}",
+                found, expected
+            ),
+            PluginError::AbiMismatch {
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "library was built against ABI version {}, expected {}",
+                found, expected
+            ),
+            PluginError::AbiMismatch {
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "library does not export `PLUGIN_ABI_VERSION`, expected {}",
+                expected
+            ),
+            PluginError::NoPlugins => write!(f, "library did not register any plugins"),
+            PluginError::DuplicateName(name) => {
+                write!(f, "a plugin named `{}` is already registered", name)
+            }
+            PluginError::MissingDependency { plugin, dependency } => write!(
+                f,
+                "plugin `{}` depends on `{}`, which isn't loaded",
+                plugin, dependency
+            ),
+            PluginError::DependencyCycle(plugins) => {
+                write!(f, "dependency cycle among {}", plugins.join(", "))
+            }
+            PluginError::Manifest(message) => write!(f, "invalid plugin manifest: {}", message),
+            PluginError::Io(err) => write!(f, "I/O error: {}", err),
+            PluginError::Panicked(message) => write!(f, "plugin panicked: {}", message),
+            PluginError::NotFound(name) => write!(f, "no plugin named `{}` is registered", name),
+            PluginError::Disabled(name) => write!(f, "plugin `{}` is disabled", name),
+            PluginError::NotReloadable(name) => {
+                write!(f, "plugin `{}` wasn't loaded from a library", name)
+            }
+            PluginError::Callback(message) => write!(f, "callback failed: {}", message),
+            PluginError::Timeout(timeout) => {
+                write!(f, "callback did not return within {:?}", timeout)
+            }
+            PluginError::InvalidInput(message) => write!(f, "invalid input: {}", message),
+            PluginError::UnknownCommand { plugin, command } => {
+                write!(f, "plugin `{}` has no command named `{}`", plugin, command)
+            }
+            PluginError::Overflow(expression) => write!(f, "{} overflows an i32", expression),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PluginError {}
+
+/// Adds `a` and `b`, returning `PluginError::Overflow` rather than panicking or wrapping when the
+/// sum doesn't fit, whatever the build profile.
+pub fn safe_add(a: i32, b: i32) -> Result<i32, PluginError> {
+    a.checked_add(b)
+        .ok_or_else(|| PluginError::Overflow(format!("{} + {}", a, b)))
+}
+
+/// Like `safe_add`, but subtracts `b` from `a`.
+pub fn safe_sub(a: i32, b: i32) -> Result<i32, PluginError> {
+    a.checked_sub(b)
+        .ok_or_else(|| PluginError::Overflow(format!("{} - {}", a, b)))
+}
+
+/// Like `safe_add`, but multiplies `a` by `b`.
+pub fn safe_mul(a: i32, b: i32) -> Result<i32, PluginError> {
+    a.checked_mul(b)
+        .ok_or_else(|| PluginError::Overflow(format!("{} * {}", a, b)))
+}
+
+/// Runs `f`, catching any panic so that a misbehaving plugin can't take down the application.
+/// The application should wrap calls into plugin code with this.
+#[cfg(feature = "std")]
+pub fn call_safely<R>(f: impl FnOnce() -> R) -> Result<R, PluginError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        PluginError::Panicked(message)
+    })
 }
@@ -0,0 +1,62 @@
+use core::{safe_add, safe_mul, safe_sub, PluginError};
+
+#[test]
+fn adds_within_range() {
+    assert_eq!(safe_add(7, 1).unwrap(), 8);
+    assert_eq!(safe_add(i32::MAX - 1, 1).unwrap(), i32::MAX);
+}
+
+#[test]
+fn reports_overflowing_addition() {
+    assert!(matches!(
+        safe_add(i32::MAX, 1),
+        Err(PluginError::Overflow(_))
+    ));
+    assert!(matches!(
+        safe_add(i32::MIN, -1),
+        Err(PluginError::Overflow(_))
+    ));
+}
+
+#[test]
+fn subtracts_within_range() {
+    assert_eq!(safe_sub(7, 1).unwrap(), 6);
+    assert_eq!(safe_sub(i32::MIN + 1, 1).unwrap(), i32::MIN);
+}
+
+#[test]
+fn reports_overflowing_subtraction() {
+    assert!(matches!(
+        safe_sub(i32::MIN, 1),
+        Err(PluginError::Overflow(_))
+    ));
+    assert!(matches!(
+        safe_sub(i32::MAX, -1),
+        Err(PluginError::Overflow(_))
+    ));
+}
+
+#[test]
+fn multiplies_within_range() {
+    assert_eq!(safe_mul(7, 2).unwrap(), 14);
+    assert_eq!(safe_mul(i32::MIN, 1).unwrap(), i32::MIN);
+    assert_eq!(safe_mul(i32::MAX, -1).unwrap(), -i32::MAX);
+}
+
+#[test]
+fn reports_overflowing_multiplication() {
+    assert!(matches!(
+        safe_mul(i32::MAX, 2),
+        Err(PluginError::Overflow(_))
+    ));
+    assert!(matches!(
+        safe_mul(i32::MIN, -1),
+        Err(PluginError::Overflow(_))
+    ));
+}
+
+#[test]
+fn describes_the_operation_that_overflowed() {
+    let err = safe_mul(i32::MAX, 2).unwrap_err();
+    assert!(err.to_string().contains(&format!("{} * 2", i32::MAX)));
+}
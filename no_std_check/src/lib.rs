@@ -0,0 +1,67 @@
+// Checks that `core` builds without `std`, by implementing its traits the way an embedded host
+// would. Build it on its own with `cargo build -p no_std_check`: building it along with the rest
+// of the workspace turns the `std` feature back on, since cargo unifies features.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use plugin_core::{
+    AsyncPlugin, Formatter, Plugin, PluginConfig, PluginId, PluginMetadata, PluginRegistrar,
+};
+
+/// A registrar that keeps the plugins it's given, and nothing else.
+#[derive(Default)]
+pub struct Registry {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Registry {
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+}
+
+impl PluginRegistrar for Registry {
+    fn register_plugin_with_config(
+        &mut self,
+        plugin: Box<dyn Plugin>,
+        _config: PluginConfig,
+    ) -> PluginId {
+        self.plugins.push(plugin);
+        PluginId::new(self.plugins.len() as u64 - 1)
+    }
+
+    fn register_formatter(&mut self, _formatter: Box<dyn Formatter>) {}
+
+    fn register_async_plugin(&mut self, _plugin: Box<dyn AsyncPlugin>) {}
+}
+
+struct Identity;
+
+impl Plugin for Identity {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Identity",
+            version: env!("CARGO_PKG_VERSION"),
+            description: None,
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, alloc::string::String> {
+        Ok(i)
+    }
+}
+
+/// Registers a plugin, and a closure plugin, with a `Registry`.
+pub fn register(registry: &mut Registry) {
+    registry.register_plugin(Box::new(Identity));
+    let registrar: &mut dyn PluginRegistrar = registry;
+    registrar.register_fn("Negate", || {}, |i| -i);
+}
@@ -0,0 +1,35 @@
+// A plugin whose callbacks panic, so the tests can check the application survives it. It panics
+// in `on_load` too when the `panic_on_load` setting is true.
+
+struct Panicky;
+
+impl core::Plugin for Panicky {
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "Panicky",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Panics in every callback"),
+        }
+    }
+
+    fn on_load(&mut self, ctx: &core::HostContext) -> Result<(), core::PluginError> {
+        if ctx.get_bool("panic_on_load") == Some(true) {
+            panic!("Panicky::on_load panicked");
+        }
+        Ok(())
+    }
+
+    fn callback1(&self) {
+        panic!("Panicky::callback1 panicked");
+    }
+
+    fn callback2(&self, _i: i32) -> Result<i32, String> {
+        panic!("Panicky::callback2 panicked");
+    }
+}
+
+fn register(registrar: &mut dyn core::PluginRegistrar, _ctx: &core::HostContext) {
+    registrar.register_plugin(Box::new(Panicky));
+}
+
+core::export_plugin!(register);
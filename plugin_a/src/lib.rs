@@ -1,17 +1,152 @@
-struct PluginA;
+struct PluginA {
+    ctx: Option<core::HostContext>,
+    /// How much `callback2` adds to its input, set with `--set increment=N`
+    increment: i32,
+    /// How many times the plugin has been loaded, which survives reloads
+    loads: u32,
+}
 
 impl core::Plugin for PluginA {
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "PluginA",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Adds an increment to its input"),
+        }
+    }
+
+    fn priority(&self) -> i32 {
+        10
+    }
+
+    fn group(&self) -> Option<&'static str> {
+        Some("math")
+    }
+
+    fn on_load(&mut self, ctx: &core::HostContext) -> Result<(), core::PluginError> {
+        self.ctx = Some(ctx.clone());
+        ctx.log().debug(&format!("args: {:?}", ctx.args()));
+        let loads = ctx
+            .state()
+            .get("loads")
+            .and_then(|loads| String::from_utf8(loads).ok()?.parse().ok())
+            .unwrap_or(0);
+        self.loads = loads + 1;
+        ctx.log().debug(&format!("loaded {} times", self.loads));
+        let handler_ctx = ctx.clone();
+        ctx.events().subscribe(
+            "ping",
+            Box::new(move |payload| {
+                handler_ctx
+                    .log()
+                    .info(&format!("got ping: {}", String::from_utf8_lossy(payload)));
+            }),
+        );
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        if let Some(ctx) = &self.ctx {
+            ctx.state()
+                .set("loads", self.loads.to_string().into_bytes());
+        }
+    }
+
+    fn callback1(&self) {
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("callback1");
+            for (fraction, message) in &[(0.0, "starting"), (0.5, "halfway there"), (1.0, "done")] {
+                if ctx.is_cancelled() {
+                    ctx.log().info("callback1 cancelled");
+                    return;
+                }
+                ctx.report_progress(*fraction, message);
+            }
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("callback2");
+        }
+        core::safe_add(i, self.increment).map_err(|err| err.to_string())
+    }
+
+    fn commands(&self) -> Vec<&'static str> {
+        vec!["double"]
+    }
+
+    fn run_command(&self, name: &str, args: &[String]) -> Result<String, String> {
+        match (name, args) {
+            ("double", [i]) => {
+                let i: i32 = i.parse().map_err(|_| format!("`{}` isn't an i32", i))?;
+                core::safe_mul(i, 2)
+                    .map(|doubled| doubled.to_string())
+                    .map_err(|err| err.to_string())
+            }
+            ("double", _) => Err("usage: double <int>".to_string()),
+            _ => Err(format!("PluginA has no command named `{}`", name)),
+        }
+    }
+}
+
+struct PluginDouble {
+    ctx: Option<core::HostContext>,
+}
+
+impl core::Plugin for PluginDouble {
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "PluginDouble",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Doubles its input"),
+        }
+    }
+
+    fn on_load(&mut self, ctx: &core::HostContext) -> Result<(), core::PluginError> {
+        self.ctx = Some(ctx.clone());
+        Ok(())
+    }
+
     fn callback1(&self) {
-        println!("PluginA::callback1")
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("callback1");
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("callback2");
+        }
+        core::safe_mul(i, 2).map_err(|err| err.to_string())
     }
+}
 
-    fn callback2(&self, i: i32) -> i32 {
-        println!("PluginA::callback2");
-        i + 1
+/// Shouts its input.
+struct Shout;
+
+impl core::Formatter for Shout {
+    fn format(&self, input: &str) -> String {
+        format!("{}!", input.to_uppercase())
     }
 }
 
-#[no_mangle]
-pub fn plugin_entry(registrar: &mut dyn core::PluginRegistrar) {
-    registrar.register_plugin(Box::new(PluginA));
+/// Registers this library's plugins. It's exported as `plugin_entry`, unless the plugin is
+/// built into the application with the `builtin` feature, in which case every plugin's exports
+/// would collide, and the application calls it directly instead.
+pub fn register(registrar: &mut dyn core::PluginRegistrar, ctx: &core::HostContext) {
+    ctx.log()
+        .debug("registering PluginA, PluginDouble and Shout");
+    registrar.register_plugins(vec![
+        Box::new(PluginA {
+            ctx: None,
+            increment: ctx.get_i32("increment").unwrap_or(1),
+            loads: 0,
+        }),
+        Box::new(PluginDouble { ctx: None }),
+    ]);
+    registrar.register_formatter(Box::new(Shout));
 }
+
+#[cfg(not(feature = "builtin"))]
+core::export_plugin!(register);
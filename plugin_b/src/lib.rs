@@ -1,17 +1,130 @@
-struct PluginB;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+struct PluginB {
+    /// A scratch file that's open while the plugin is loaded
+    scratch: Option<(PathBuf, File)>,
+    ctx: Option<core::HostContext>,
+    /// Set once `callback2` has run, after which the plugin reports itself as degraded
+    called: AtomicBool,
+}
 
 impl core::Plugin for PluginB {
-    fn callback1(&self) {
-        println!("PluginB::callback1")
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "PluginB",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Subtracts one from its input"),
+        }
+    }
+
+    fn priority(&self) -> i32 {
+        -5
+    }
+
+    fn dependencies(&self) -> Vec<&'static str> {
+        vec!["PluginA"]
+    }
+
+    fn on_load(&mut self, ctx: &core::HostContext) -> Result<(), core::PluginError> {
+        self.ctx = Some(ctx.clone());
+        let path = std::env::temp_dir().join(format!("plugin_b-{}.tmp", std::process::id()));
+        let file = File::create(&path).map_err(|err| core::PluginError::Io(err.to_string()))?;
+        self.scratch = Some((path, file));
+        Ok(())
     }
 
-    fn callback2(&self, i: i32) -> i32 {
-        println!("PluginB::callback2");
-        i - 1
+    fn on_unload(&mut self) {
+        if let Some((path, file)) = self.scratch.take() {
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn capabilities(&self) -> core::Capabilities {
+        core::Capabilities::HANDLES_CALLBACK2
+    }
+
+    fn group(&self) -> Option<&'static str> {
+        Some("math")
+    }
+
+    fn schema(&self) -> core::CallbackSchema {
+        core::CallbackSchema {
+            callback2_input: core::ValueSchema::I32.with_min(1),
+            callback2_output: core::ValueSchema::I32.with_min(0),
+        }
+    }
+
+    fn health(&self) -> core::Health {
+        if self.called.load(Ordering::Relaxed) {
+            core::Health::Degraded("callback2 has already been called".to_string())
+        } else {
+            core::Health::Ok
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        self.called.store(true, Ordering::Relaxed);
+        if let Some((_, file)) = &self.scratch {
+            let mut file: &File = file;
+            let _ = writeln!(file, "callback2({})", i);
+        }
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("callback2");
+            ctx.events().publish("ping", b"from PluginB");
+        }
+        if i < 1 {
+            return Err(format!("input must be at least 1, got {}", i));
+        }
+        core::safe_sub(i, 1).map_err(|err| err.to_string())
+    }
+}
+
+/// Subtracts one from its input, asynchronously.
+struct PluginCountdown;
+
+#[core::async_trait]
+impl core::AsyncPlugin for PluginCountdown {
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "PluginCountdown",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Subtracts one from its input, asynchronously"),
+        }
+    }
+
+    async fn callback2(&self, i: i32) -> i32 {
+        // `AsyncPlugin::callback2` can't fail, so stop at the bottom instead.
+        i.saturating_sub(1)
     }
 }
 
-#[no_mangle]
-pub fn plugin_entry(registrar: &mut dyn core::PluginRegistrar) {
-    registrar.register_plugin(Box::new(PluginB));
+/// Wraps its input in brackets.
+struct Bracket;
+
+impl core::Formatter for Bracket {
+    fn format(&self, input: &str) -> String {
+        format!("[{}]", input)
+    }
 }
+
+/// Registers this library's plugins. It's exported as `plugin_entry`, unless the plugin is
+/// built into the application with the `builtin` feature, in which case every plugin's exports
+/// would collide, and the application calls it directly instead.
+pub fn register(registrar: &mut dyn core::PluginRegistrar, ctx: &core::HostContext) {
+    ctx.log()
+        .debug("registering PluginB, PluginCountdown and Bracket");
+    registrar.register_plugin(Box::new(PluginB {
+        scratch: None,
+        ctx: None,
+        called: AtomicBool::new(false),
+    }));
+    registrar.register_async_plugin(Box::new(PluginCountdown));
+    registrar.register_formatter(Box::new(Bracket));
+}
+
+#[cfg(not(feature = "builtin"))]
+core::export_plugin!(register);
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::SortOrder;
+
+/// The plugin libraries to load, each with the arguments to hand to its plugins.
+pub type PluginPaths = Vec<(PathBuf, Vec<String>)>;
+
+/// The options the application's command line sets, besides the plugin paths.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Flags {
+    /// Directories to load every plugin in, from `--plugin-dir`.
+    pub dirs: Vec<PathBuf>,
+    /// A `plugins.toml` manifest to load plugins from, from `--manifest`.
+    pub manifest: Option<PathBuf>,
+    /// The configuration handed to plugins, from `--set key=value`.
+    pub config: HashMap<String, String>,
+    pub check: bool,
+    pub repl: bool,
+    pub strict: bool,
+    pub group: Option<String>,
+    pub verbose: bool,
+    pub sequential: bool,
+    pub json: bool,
+    /// The only plugins to register, from `--allow`, or `None` to register every plugin.
+    pub allow: Option<Vec<String>>,
+    pub allow_multiple_versions: bool,
+    pub sort_by: SortOrder,
+}
+
+/// Parses the application's arguments, not including the program name.
+///
+/// Arguments are plugin paths, except for:
+/// - `--plugin-dir DIR` which loads every plugin in a directory
+/// - `--manifest FILE` which loads the plugins listed in a `plugins.toml` manifest
+/// - `--set key=value` which adds to the configuration handed to plugins
+/// - `--check` which only loads the plugins and reports on them, without running them
+/// - `--strict` which exits with a non-zero status if any plugin library or directory fails to
+///   load
+/// - `--repl` which loads the plugins and then reads commands for them from stdin, instead of
+///   running them
+/// - `--verbose` which prints a summary of the loaded plugins before running them, and any
+///   unhealthy plugins afterwards
+/// - `--sequential` which calls the plugins' `callback1` one at a time instead of in parallel
+/// - `--sort-by name|priority` which runs the plugins in order of the path they were loaded
+///   from, the default, or in order of priority. Either way, plugins run after the plugins
+///   they depend on
+/// - `--group GROUP` which only calls `callback2` on the plugins in a group, and prints
+///   their results
+/// - `--json` which prints the plugins' `callback2` results as JSON
+/// - `--allow NAME[,NAME...]` which only registers the plugins named, and can be repeated
+/// - `--allow-multiple-versions` which keeps every version of a plugin that's loaded more
+///   than once, rather than only the newest
+///
+/// A plugin path can be followed by `--` and arguments for the plugins in that library, e.g.
+/// `plugin_a.so -- --foo=1 plugin_b.so -- --bar=2`. Its arguments run up to the next argument
+/// that doesn't start with `-`, or the next `--`, and are handed to the plugins through
+/// `HostContext::args`. Malformed options are logged and ignored.
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> (PluginPaths, Flags) {
+    let mut paths: PluginPaths = Vec::new();
+    let mut flags = Flags::default();
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--") => {
+                let mut plugin_args = Vec::new();
+                while let Some(plugin_arg) = args.next_if(|arg| {
+                    let arg = arg.to_string_lossy();
+                    arg.starts_with('-') && arg != "--"
+                }) {
+                    plugin_args.push(plugin_arg.to_string_lossy().into_owned());
+                }
+                match paths.last_mut() {
+                    Some((_, path_args)) => path_args.extend(plugin_args),
+                    None => log::warn!("ignoring `--` before any plugin path"),
+                }
+            }
+            Some("--plugin-dir") => match args.next() {
+                Some(dir) => flags.dirs.push(PathBuf::from(dir)),
+                None => log::warn!("ignoring `--plugin-dir` without a directory"),
+            },
+            Some("--manifest") => match args.next() {
+                Some(path) => flags.manifest = Some(PathBuf::from(path)),
+                None => log::warn!("ignoring `--manifest` without a file"),
+            },
+            Some("--check") => flags.check = true,
+            Some("--repl") => flags.repl = true,
+            Some("--strict") => flags.strict = true,
+            Some("--group") => match args.next().and_then(|name| name.into_string().ok()) {
+                Some(name) => flags.group = Some(name),
+                None => log::warn!("ignoring `--group` without a group"),
+            },
+            Some("--verbose") => flags.verbose = true,
+            Some("--sequential") => flags.sequential = true,
+            Some("--json") => flags.json = true,
+            Some("--sort-by") => match args.next().as_ref().and_then(|order| order.to_str()) {
+                Some("name") => flags.sort_by = SortOrder::Name,
+                Some("priority") => flags.sort_by = SortOrder::Priority,
+                _ => log::warn!("ignoring `--sort-by`, expected `name` or `priority`"),
+            },
+            Some("--allow-multiple-versions") => flags.allow_multiple_versions = true,
+            Some("--allow") => match args.next().and_then(|names| names.into_string().ok()) {
+                Some(names) => flags
+                    .allow
+                    .get_or_insert_with(Vec::new)
+                    .extend(names.split(',').map(str::to_string)),
+                None => log::warn!("ignoring `--allow` without plugin names"),
+            },
+            Some("--set") => {
+                let setting = args.next().and_then(|setting| setting.into_string().ok());
+                match setting.as_ref().and_then(|setting| setting.split_once('=')) {
+                    Some((key, value)) => {
+                        flags.config.insert(key.to_string(), value.to_string());
+                    }
+                    None => log::warn!("ignoring malformed `--set`, expected `key=value`"),
+                }
+            }
+            _ => paths.push((PathBuf::from(arg), Vec::new())),
+        }
+    }
+    (paths, flags)
+}
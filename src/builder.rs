@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use core::{HostConfig, HostContext, HostLogger, Plugin, ProgressSink};
+
+use crate::metrics::{MetricsSink, NoMetrics};
+use crate::{ForwardingLogger, PluginManager, ProgressLogger, RegisterHook};
+
+/// Configures a `PluginManager`, e.g.
+/// `PluginManagerBuilder::new().with_panic_isolation(true).build()`. The defaults match
+/// `PluginManager::new`, with a `ForwardingLogger`, a `ProgressLogger` and an empty
+/// configuration.
+pub struct PluginManagerBuilder {
+    logger: Arc<dyn HostLogger>,
+    progress: Arc<dyn ProgressSink>,
+    config: Arc<dyn HostConfig>,
+    metrics: Arc<dyn MetricsSink>,
+    abi_check: bool,
+    panic_isolation: bool,
+    timeout: Option<Duration>,
+    failure_threshold: Option<u32>,
+    on_register: Option<RegisterHook>,
+}
+
+impl PluginManagerBuilder {
+    pub fn new() -> Self {
+        PluginManagerBuilder {
+            logger: Arc::new(ForwardingLogger),
+            progress: Arc::new(ProgressLogger),
+            config: Arc::new(HashMap::new()),
+            metrics: Arc::new(NoMetrics),
+            abi_check: true,
+            panic_isolation: false,
+            timeout: None,
+            failure_threshold: None,
+            on_register: None,
+        }
+    }
+
+    /// Whether to refuse libraries that don't export the expected `PLUGIN_ABI_VERSION`. On by
+    /// default. Turning it off is only safe when every library is known to be up to date.
+    pub fn with_abi_check(mut self, enabled: bool) -> Self {
+        self.abi_check = enabled;
+        self
+    }
+
+    /// Whether to catch panics raised while loading and calling plugins. A library whose
+    /// `plugin_entry` panics, or a plugin whose `on_load` panics, is then skipped instead of
+    /// taking down the application. Off by default.
+    pub fn with_panic_isolation(mut self, enabled: bool) -> Self {
+        self.panic_isolation = enabled;
+        self
+    }
+
+    /// How long `PluginManager::call_callback2` waits for a plugin before giving up. By default
+    /// it waits forever.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How many times in a row a plugin's `callback2` may fail, through
+    /// `PluginManager::call_callback2`, `call_with_timeout` or `map_callback2`, before the plugin
+    /// is disabled. By default plugins are never disabled.
+    pub fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = Some(failures.max(1));
+        self
+    }
+
+    /// Called with every plugin before it's registered, to enforce a policy such as an
+    /// allowlist. Plugins it returns `false` for are dropped, and logged, and listed by
+    /// `PluginManager::vetoed`, but don't make their library fail to load. By default every
+    /// plugin is registered. Formatters and async plugins aren't `Plugin`s, so the hook never
+    /// sees them, and they're always registered.
+    pub fn on_register<F>(mut self, on_register: F) -> Self
+    where
+        F: Fn(&dyn Plugin) -> bool + Send + Sync + 'static,
+    {
+        self.on_register = Some(Box::new(on_register));
+        self
+    }
+
+    /// The logger plugins log through.
+    pub fn log_sink(mut self, logger: Box<dyn HostLogger>) -> Self {
+        self.logger = logger.into();
+        self
+    }
+
+    /// Where plugins' progress updates go.
+    pub fn progress_sink(mut self, progress: Box<dyn ProgressSink>) -> Self {
+        self.progress = progress.into();
+        self
+    }
+
+    /// Where the manager's counters go, see `metrics`. Keep a clone of `metrics` to read them
+    /// back, e.g. from a `CountingSink`. By default they're discarded.
+    pub fn metrics_sink(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The configuration handed to plugins.
+    pub fn with_config(mut self, config: Arc<dyn HostConfig>) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> PluginManager {
+        let context = HostContext::new(self.logger, self.config).with_progress(self.progress);
+        let mut manager = PluginManager::new(context);
+        manager.abi_check = self.abi_check;
+        manager.panic_isolation = self.panic_isolation;
+        manager.timeout = self.timeout;
+        manager.failure_threshold = self.failure_threshold;
+        manager.on_register = self.on_register;
+        manager.metrics = self.metrics;
+        manager
+    }
+}
+
+impl Default for PluginManagerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+
+use core::{EventBus, EventHandler};
+
+type Handler = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+struct Subscription {
+    /// The library whose code `handler` lives in, or `None` if it's part of the application
+    owner: Option<u64>,
+    topic: String,
+    handler: Handler,
+}
+
+/// The event bus shared by every plugin a `PluginManager` loads. Subscriptions are tagged with
+/// the library they came from, so they can be removed before that library is closed.
+#[derive(Default)]
+pub(crate) struct LocalEventBus {
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl LocalEventBus {
+    /// Returns a view of the bus for the library `owner` to publish and subscribe through.
+    pub(crate) fn scoped(self: &Arc<Self>, owner: Option<u64>) -> Arc<dyn EventBus> {
+        Arc::new(ScopedEventBus {
+            bus: self.clone(),
+            owner,
+        })
+    }
+
+    /// Removes every subscription made by the library `owner`.
+    pub(crate) fn unsubscribe_owner(&self, owner: u64) {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .retain(|subscription| subscription.owner != Some(owner));
+    }
+
+    fn publish(&self, topic: &str, payload: &[u8]) {
+        // Collect the handlers first, so a handler is free to publish or subscribe itself.
+        let handlers: Vec<Handler> = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|subscription| subscription.topic == topic)
+            .map(|subscription| subscription.handler.clone())
+            .collect();
+        for handler in handlers {
+            handler(payload);
+        }
+    }
+}
+
+struct ScopedEventBus {
+    bus: Arc<LocalEventBus>,
+    owner: Option<u64>,
+}
+
+impl EventBus for ScopedEventBus {
+    fn publish(&self, topic: &str, payload: &[u8]) {
+        self.bus.publish(topic, payload);
+    }
+
+    fn subscribe(&self, topic: &str, handler: EventHandler) {
+        self.bus.subscriptions.lock().unwrap().push(Subscription {
+            owner: self.owner,
+            topic: topic.to_string(),
+            handler: handler.into(),
+        });
+    }
+}
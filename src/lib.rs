@@ -0,0 +1,1488 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::future;
+use libloading::Library;
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
+use semver::Version;
+use serde::Serialize;
+use tempfile::TempPath;
+
+use core::{
+    AsyncPlugin, CancellationToken, Capabilities, Formatter, Health, HostContext, HostLogger,
+    LogLevel, Plugin, PluginConfig, PluginError, PluginId, PluginRegistrar, ProgressSink,
+};
+
+pub mod args;
+mod builder;
+mod events;
+mod manifest;
+pub mod metrics;
+pub mod repl;
+mod state;
+
+pub use builder::PluginManagerBuilder;
+pub use metrics::{CountingSink, MetricsSink};
+
+use events::LocalEventBus;
+use state::LocalStateStore;
+
+/// `PluginManager` owns every plugin library the application has loaded, along with the plugins
+/// those libraries registered. Plugins are always dropped before the library containing their
+/// code is closed, so no plugin code can run after it has been unmapped.
+pub struct PluginManager {
+    context: HostContext,
+    events: Arc<LocalEventBus>,
+    state: Arc<LocalStateStore>,
+    plugins: Vec<LoadedPlugin>,
+    formatters: Vec<LoadedFormatter>,
+    async_plugins: Vec<LoadedAsyncPlugin>,
+    libraries: Vec<LoadedLibrary>,
+    next_library_id: u64,
+    next_plugin_id: u64,
+    /// Why `register_plugin` rejected plugins while the current library was being loaded
+    rejected: Vec<PluginError>,
+    /// Libraries that failed to load
+    failed: Vec<PathBuf>,
+    /// The names of the plugins the `on_register` hook refused to register
+    vetoed: Vec<String>,
+    /// Whether to refuse libraries with the wrong `PLUGIN_ABI_VERSION`
+    abi_check: bool,
+    /// Whether to catch panics raised by `plugin_entry`, `on_load` and `call_callback2`
+    panic_isolation: bool,
+    /// How long `call_callback2` waits for a plugin
+    timeout: Option<Duration>,
+    /// How many times in a row `call_callback2` may fail before the plugin is disabled
+    failure_threshold: Option<u32>,
+    /// Decides whether each plugin may be registered
+    on_register: Option<RegisterHook>,
+    metrics: Arc<dyn MetricsSink>,
+    /// The libraries `poll_reloads` reloads
+    watch: Option<Watch>,
+}
+
+/// Decides whether a plugin may be registered, see `PluginManagerBuilder::on_register`.
+pub type RegisterHook = Box<dyn Fn(&dyn Plugin) -> bool + Send + Sync>;
+
+impl PluginManager {
+    /// Creates an empty manager. `context` is handed to every plugin library it loads, with its
+    /// event bus replaced by one shared between the plugins this manager loads. Use
+    /// `PluginManagerBuilder` to configure the manager further.
+    pub fn new(context: HostContext) -> Self {
+        PluginManager {
+            context,
+            events: Arc::new(LocalEventBus::default()),
+            state: Arc::new(LocalStateStore::default()),
+            plugins: Vec::new(),
+            formatters: Vec::new(),
+            async_plugins: Vec::new(),
+            libraries: Vec::new(),
+            next_library_id: 0,
+            next_plugin_id: 0,
+            rejected: Vec::new(),
+            failed: Vec::new(),
+            vetoed: Vec::new(),
+            abi_check: true,
+            panic_isolation: false,
+            timeout: None,
+            failure_threshold: None,
+            on_register: None,
+            metrics: Arc::new(metrics::NoMetrics),
+            watch: None,
+        }
+    }
+
+    /// Loads the plugin library at `path` and registers every plugin it provides. A library
+    /// that doesn't register any plugins is an error.
+    pub fn load_plugin(&mut self, path: &Path) -> Result<(), PluginError> {
+        self.load_plugin_with_args(path, Vec::new())
+    }
+
+    /// Like `load_plugin`, but hands `args` to the library's plugins through `HostContext::args`.
+    /// A library that's already loaded, even through a different path, is skipped with a
+    /// warning rather than registering its plugins twice.
+    pub fn load_plugin_with_args(
+        &mut self,
+        path: &Path,
+        args: Vec<String>,
+    ) -> Result<(), PluginError> {
+        if let Some(loaded) = self.loaded_from(path) {
+            log::warn!(
+                "skipping {}: already loaded from {}",
+                path.display(),
+                loaded.display()
+            );
+            return Ok(());
+        }
+        let result = self.open_library(path, args);
+        if result.is_err() {
+            self.failed.push(path.to_path_buf());
+            self.metrics.incr(metrics::LOADS_FAILED, 1);
+        }
+        self.count_panic(result)
+    }
+
+    /// Loads each of the plugin libraries at `paths` in turn, like `load_plugin`, and reports
+    /// what happened to each one.
+    pub fn load_all(&mut self, paths: &[PathBuf]) -> LoadReport {
+        self.load_each(paths.iter().map(|path| (path.as_path(), Vec::new())))
+    }
+
+    /// Like `load_all`, but hands each library its own arguments, like `load_plugin_with_args`.
+    pub fn load_all_with_args(&mut self, paths: &[(PathBuf, Vec<String>)]) -> LoadReport {
+        self.load_each(
+            paths
+                .iter()
+                .map(|(path, args)| (path.as_path(), args.clone())),
+        )
+    }
+
+    fn load_each<'a>(
+        &mut self,
+        paths: impl IntoIterator<Item = (&'a Path, Vec<String>)>,
+    ) -> LoadReport {
+        let mut report = LoadReport::default();
+        for (path, args) in paths {
+            let before = self.plugins.len();
+            let vetoed_before = self.vetoed.len();
+            let skipped = self.loaded_from(path).is_some();
+            let start = Instant::now();
+            let result = self.load_plugin_with_args(path, args);
+            report.timings.push((path.to_path_buf(), start.elapsed()));
+            if skipped {
+                report.skipped.push(path.to_path_buf());
+            }
+            report.loaded.extend(
+                self.plugins[before..]
+                    .iter()
+                    .map(|loaded| loaded.name().to_string()),
+            );
+            report
+                .vetoed
+                .extend_from_slice(&self.vetoed[vetoed_before..]);
+            if let Err(err) = result {
+                report.failed.push((path.to_path_buf(), err));
+            }
+        }
+        report
+    }
+
+    /// The path the library at `path` was loaded from, if it's already loaded.
+    fn loaded_from(&self, path: &Path) -> Option<&Path> {
+        self.libraries
+            .iter()
+            .find(|library| same_file(&library.path, path))
+            .map(|library| library.path.as_path())
+    }
+
+    fn open_library(&mut self, path: &Path, args: Vec<String>) -> Result<(), PluginError> {
+        let before = self.plugins.len();
+        let formatters_before = self.formatters.len();
+        let async_before = self.async_plugins.len();
+        let vetoed_before = self.vetoed.len();
+        let id = self.next_library_id;
+        self.next_library_id += 1;
+        let lib = LoadedLibrary {
+            path: path.to_path_buf(),
+            id,
+            events: self.events.clone(),
+            library: Arc::new(
+                Library::new(path).map_err(|err| PluginError::LibraryOpen(err.to_string()))?,
+            ),
+            args: args.clone(),
+            temp: None,
+        };
+        let context = self.context_for(Some(id)).with_args(args);
+        // NOTE: You need to do something to ensure you're only loading "safe" code. Out of scope
+        // for this code.
+        unsafe {
+            let found = lib
+                .library
+                .get::<*const u32>(b"PLUGIN_ABI_VERSION")
+                .ok()
+                .map(|version| **version);
+            if self.abi_check && found != Some(core::ABI_VERSION) {
+                return Err(PluginError::AbiMismatch {
+                    expected: core::ABI_VERSION,
+                    found,
+                });
+            }
+            if let Ok(version) = lib.library.get::<*const &str>(b"RUSTC_VERSION") {
+                let version: &str = **version;
+                if !core::rustc_version_matches(version) {
+                    log::warn!(
+                        "{} was built with {}, but the application was built with {}",
+                        path.display(),
+                        version,
+                        core::RUSTC_VERSION
+                    );
+                }
+            }
+
+            match lib.library.get::<*const u64>(b"PLUGIN_ENTRY_SIGNATURE") {
+                Ok(found) if **found != core::ENTRY_SIGNATURE_HASH => {
+                    return Err(PluginError::EntrySignatureMismatch {
+                        expected: core::ENTRY_SIGNATURE_HASH,
+                        found: **found,
+                    });
+                }
+                Ok(_) => {}
+                Err(_) => log::warn!(
+                    "{} does not export `PLUGIN_ENTRY_SIGNATURE`, calling its `plugin_entry` \
+                     unchecked",
+                    path.display()
+                ),
+            }
+
+            // `plugin_entry` is an ordinary Rust function, so it's called with the Rust ABI,
+            // which isn't stable: a library built with a different compiler is only warned
+            // about above, and calling it may well misbehave.
+            let func: libloading::Symbol<core::PluginEntry> = lib
+                .library
+                .get(b"plugin_entry")
+                .map_err(|_| PluginError::MissingEntrypoint)?;
+            self.rejected.clear();
+            let isolate = self.panic_isolation;
+            let entered = guard(isolate, || {
+                func(
+                    &mut Registrar {
+                        manager: self,
+                        source: path,
+                        library: &lib.library,
+                        context: &context,
+                    },
+                    &context,
+                )
+            });
+            if let Err(err) = entered {
+                // Don't keep half of a library's plugins.
+                for loaded in self.plugins.drain(before..) {
+                    loaded.unload();
+                }
+                self.formatters.truncate(formatters_before);
+                self.async_plugins.truncate(async_before);
+                return Err(err);
+            }
+        }
+        let registered = self.plugins.len() - before + self.formatters.len() - formatters_before
+            + self.async_plugins.len()
+            - async_before;
+        if registered > 0 {
+            self.libraries.push(lib);
+        }
+        // A library whose plugins were all vetoed did what it should, so it's closed quietly.
+        let vetoed = self.vetoed.len() - vetoed_before;
+        match self.rejected.pop() {
+            Some(err) => Err(err),
+            None if registered == 0 && vetoed == 0 => Err(PluginError::NoPlugins),
+            None => Ok(()),
+        }
+    }
+
+    /// Registers the plugins linked into the application by the `static-plugins` feature, by
+    /// calling the functions they'd export as `plugin_entry` directly.
+    #[cfg(feature = "static-plugins")]
+    pub fn register_builtin_plugins(&mut self) {
+        let context = self.context_for(None);
+        plugin_a::register(self, &context);
+        plugin_b::register(self, &context);
+    }
+
+    /// Loads a plugin library from its contents rather than from a file, for when it was
+    /// received over the network, say. The library is written to a temporary file, since it has
+    /// to be opened from one, which is deleted when the library is unloaded.
+    ///
+    /// Loading a library runs its code, so only load bytes from a source you trust, and check
+    /// them first if you can: receiving them from elsewhere makes that no less important.
+    pub fn load_from_bytes(&mut self, bytes: &[u8]) -> Result<(), PluginError> {
+        let mut file = tempfile::Builder::new()
+            .prefix("plugin-")
+            .suffix(std::env::consts::DLL_SUFFIX)
+            .tempfile()
+            .map_err(|err| PluginError::Io(err.to_string()))?;
+        file.write_all(bytes)
+            .map_err(|err| PluginError::Io(err.to_string()))?;
+        let temp = file.into_temp_path();
+        let result = self.open_library(&temp, Vec::new());
+        if result.is_err() {
+            self.failed.push(temp.to_path_buf());
+            self.metrics.incr(metrics::LOADS_FAILED, 1);
+        }
+        if let Some(library) = self.libraries.last_mut() {
+            if library.path == *temp {
+                library.temp = Some(temp);
+            }
+        }
+        self.count_panic(result)
+    }
+
+    /// Loads every plugin library found directly inside `dir`, in order of their file names,
+    /// and reports what happened to each one, like `load_all`. Files that aren't dynamic
+    /// libraries are skipped silently, while libraries that fail to load are skipped and listed
+    /// in the report's `failed`.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<LoadReport, PluginError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|err| PluginError::ReadDir(err.to_string()))? {
+            let path = entry
+                .map_err(|err| PluginError::ReadDir(err.to_string()))?
+                .path();
+            if path.is_file() && path.extension() == Some(OsStr::new(core::DYLIB_EXTENSION)) {
+                paths.push(path);
+            }
+        }
+        // Directory entries come back in no particular order, which varies between platforms.
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        Ok(self.load_all(&paths))
+    }
+
+    /// Starts watching the plugin libraries at `paths`, replacing any previous watch, so
+    /// `poll_reloads` can reload them once they're rewritten on disk.
+    pub fn watch(&mut self, paths: &[PathBuf]) -> Result<(), PluginError> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+        // Watch the containing directories rather than the files themselves: build tools tend to
+        // replace a library instead of rewriting it, which would end a watch on the old file.
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+            .collect();
+        for path in &paths {
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+        self.watch = Some(Watch {
+            _watcher: watcher,
+            events: Mutex::new(rx),
+            paths,
+        });
+        Ok(())
+    }
+
+    /// Reloads each watched library that has been rewritten since the last call: its plugins are
+    /// unloaded, the library is closed, and `plugin_entry` and `on_load` run again from the
+    /// fresh copy. Returns the paths of the libraries that were reloaded; failures are logged.
+    /// This returns straight away when nothing has changed, so call it now and then between
+    /// running plugins, e.g. once per iteration of the application's main loop.
+    ///
+    /// Note that the platform may keep a library mapped after it has been closed (for example
+    /// when it registered thread-local destructors), in which case the old code is reused.
+    pub fn poll_reloads(&mut self) -> Vec<PathBuf> {
+        let changed = match &self.watch {
+            Some(watch) => watch.changed(),
+            None => return Vec::new(),
+        };
+        let mut reloaded = Vec::new();
+        for path in changed {
+            match self.reload_library(&path) {
+                Ok(()) => {
+                    log::info!("reloaded {}", path.display());
+                    reloaded.push(path);
+                }
+                Err(err) => log::warn!("failed to reload {}: {}", path.display(), err),
+            }
+        }
+        reloaded
+    }
+
+    /// Reloads the library the plugin named `name` was loaded from, as `poll_reloads` does
+    /// when it's rewritten, leaving other libraries' plugins alone. A library can only be
+    /// reopened once all of its plugins are gone, so every plugin it registered is reloaded
+    /// along with the named one.
+    ///
+    /// Reloaded plugins keep their place in the order the manager runs plugins in, and the
+    /// `PluginId` they were registered under. Plugins the fresh library no longer registers are
+    /// dropped, and their ids no longer refer to anything; new ones are added at the end.
+    pub fn reload(&mut self, name: &str) -> Result<(), PluginError> {
+        if self.get(name).is_none() {
+            return Err(PluginError::NotFound(name.to_string()));
+        }
+        let path = self
+            .source_path(name)
+            .ok_or_else(|| PluginError::NotReloadable(name.to_string()))?
+            .to_path_buf();
+        self.reload_library(&path)
+    }
+
+    /// Unloads the plugins registered by the library at `path`, closes it, and loads it again,
+    /// putting the plugins that come back where they were. Opening the library is retried a
+    /// few times in case it's still being written.
+    fn reload_library(&mut self, path: &Path) -> Result<(), PluginError> {
+        let mut args = Vec::new();
+        // Where each of the library's plugins was, by name, in ascending order of position
+        let mut previous: Vec<(usize, String, PluginId)> = Vec::new();
+        // A library loaded by `load_from_bytes` is reopened from its temporary file, so keep
+        // the file around while the library is closed.
+        let mut temp = None;
+        if let Some(library) = self
+            .libraries
+            .iter_mut()
+            .find(|library| same_file(&library.path, path))
+        {
+            let source = library.path.clone();
+            args = library.args.clone();
+            temp = library.temp.take();
+            previous = self
+                .plugins
+                .iter()
+                .enumerate()
+                .filter(|(_, loaded)| loaded.source.as_ref() == Some(&source))
+                .map(|(index, loaded)| (index, loaded.name().to_string(), loaded.id))
+                .collect();
+            for (_, name, _) in &previous {
+                self.unload(name);
+            }
+            // Unloading its last plugin closes the library, unless it only registered
+            // formatters or async plugins.
+            self.close_library(&source);
+        }
+
+        let before = self.plugins.len();
+        let mut attempt = 1;
+        let result = loop {
+            match self.open_library(path, args.clone()) {
+                Err(PluginError::LibraryOpen(_))
+                | Err(PluginError::MissingEntrypoint)
+                | Err(PluginError::AbiMismatch { .. })
+                    if attempt < RELOAD_ATTEMPTS =>
+                {
+                    attempt += 1;
+                    thread::sleep(RELOAD_DELAY);
+                }
+                result => break result,
+            }
+        };
+        if let Some(temp) = temp {
+            if let Some(library) = self
+                .libraries
+                .iter_mut()
+                .find(|library| library.path == *temp)
+            {
+                library.temp = Some(temp);
+            }
+        }
+
+        let mut fresh: Vec<LoadedPlugin> = self.plugins.drain(before..).collect();
+        for (index, name, id) in previous {
+            if let Some(position) = fresh.iter().position(|loaded| loaded.name() == name) {
+                let mut loaded = fresh.remove(position);
+                loaded.id = id;
+                let index = index.min(self.plugins.len());
+                self.plugins.insert(index, loaded);
+            }
+        }
+        self.plugins.extend(fresh);
+        result
+    }
+
+    /// The names of the plugins the `on_register` hook refused to register, in the order they
+    /// were offered. Vetoed plugins aren't failures: a library whose plugins were all vetoed
+    /// still loads successfully, although it has nothing left to keep it open.
+    pub fn vetoed(&self) -> &[String] {
+        &self.vetoed
+    }
+
+    /// The paths of the libraries that have failed to load, in the order they were attempted.
+    /// Libraries loaded with `load_from_bytes` are listed under the temporary file they were
+    /// written to, which has since been deleted.
+    pub fn failed_paths(&self) -> &[PathBuf] {
+        &self.failed
+    }
+
+    /// Iterates over the registered plugins in registration order.
+    pub fn plugins(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.plugins.iter().map(|loaded| loaded.plugin.as_ref())
+    }
+
+    /// Iterates over the registered plugins in ascending order of priority. Plugins with the
+    /// same priority are kept in registration order.
+    pub fn iter_by_priority(&self) -> impl Iterator<Item = &dyn Plugin> {
+        let mut plugins: Vec<&dyn Plugin> = self.plugins().collect();
+        plugins.sort_by_key(|plugin| plugin.priority());
+        plugins.into_iter()
+    }
+
+    /// Among the plugins that share a name, unloads all but those with the highest version,
+    /// returning the names of the plugins unloaded. Versions are compared as semver, with
+    /// versions that don't parse as the lowest. Call this before `resolve_dependencies`.
+    ///
+    /// A plugin registered while another version of it was loaded is named `name@version`. If
+    /// it's the one that's kept, it takes back its plain name, but it still logs, and keeps its
+    /// state, under the name it was registered with.
+    pub fn dedup_by_name_keeping_newest(&mut self) -> Vec<String> {
+        fn version(loaded: &LoadedPlugin) -> Option<Version> {
+            Version::parse(loaded.plugin.metadata().version).ok()
+        }
+
+        let mut newest: HashMap<String, Option<Version>> = HashMap::new();
+        for loaded in &self.plugins {
+            let version = version(loaded);
+            newest
+                .entry(loaded.plugin.metadata().name.to_string())
+                .and_modify(|newest| {
+                    if version > *newest {
+                        *newest = version.clone();
+                    }
+                })
+                .or_insert(version);
+        }
+
+        let older: Vec<String> = self
+            .plugins
+            .iter()
+            .filter(|loaded| version(loaded) < newest[loaded.plugin.metadata().name])
+            .map(|loaded| loaded.name().to_string())
+            .collect();
+        for name in &older {
+            self.unload(name);
+        }
+
+        for index in 0..self.plugins.len() {
+            let metadata = self.plugins[index].plugin.metadata();
+            if self.plugins[index].name() == versioned_name(metadata.name, metadata.version)
+                && self.get(metadata.name).is_none()
+            {
+                self.plugins[index].name = metadata.name.to_string();
+            }
+        }
+        older
+    }
+
+    /// Orders the registered plugins so each one comes after the plugins it depends on, using
+    /// priority and then registration order to break ties. Plugins with a dependency that isn't
+    /// loaded, or that are caught in a dependency cycle, are unloaded and reported in the
+    /// returned errors.
+    pub fn resolve_dependencies(&mut self) -> Vec<PluginError> {
+        self.resolve_dependencies_by(SortOrder::Priority)
+    }
+
+    /// Like `resolve_dependencies`, but breaks ties between plugins in the given `order`.
+    pub fn resolve_dependencies_by(&mut self, order: SortOrder) -> Vec<PluginError> {
+        let mut errors = Vec::new();
+
+        // Unloading a plugin can strand the plugins that depend on it, so repeat until every
+        // remaining dependency is satisfied.
+        while let Some((plugin, dependency)) = self.plugins.iter().find_map(|loaded| {
+            loaded
+                .plugin
+                .dependencies()
+                .into_iter()
+                .find(|dependency| self.get(dependency).is_none())
+                .map(|dependency| (loaded.name().to_string(), dependency.to_string()))
+        }) {
+            self.unload(&plugin);
+            errors.push(PluginError::MissingDependency { plugin, dependency });
+        }
+
+        let mut remaining: Vec<LoadedPlugin> = self.plugins.drain(..).collect();
+        let mut sorted: Vec<LoadedPlugin> = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let ready = remaining
+                .iter()
+                .enumerate()
+                .filter(|(_, loaded)| {
+                    loaded.plugin.dependencies().iter().all(|dependency| {
+                        sorted.iter().any(|resolved| resolved.name() == *dependency)
+                    })
+                })
+                .min_by(|(a_index, a), (b_index, b)| match order {
+                    SortOrder::Name => (&a.source, a_index).cmp(&(&b.source, b_index)),
+                    SortOrder::Priority => {
+                        (a.plugin.priority(), a_index).cmp(&(b.plugin.priority(), b_index))
+                    }
+                })
+                .map(|(index, _)| index);
+            match ready {
+                Some(index) => sorted.push(remaining.remove(index)),
+                None => {
+                    errors.push(PluginError::DependencyCycle(
+                        remaining
+                            .iter()
+                            .map(|loaded| loaded.name().to_string())
+                            .collect(),
+                    ));
+                    for loaded in remaining.drain(..) {
+                        loaded.unload();
+                    }
+                }
+            }
+        }
+        self.plugins = sorted;
+        errors
+    }
+
+    /// Looks up a registered plugin by the name it was registered under.
+    pub fn get(&self, name: &str) -> Option<&dyn Plugin> {
+        self.plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)
+            .map(|loaded| loaded.plugin.as_ref())
+    }
+
+    /// Looks up a registered plugin by the id `register_plugin` returned for it.
+    pub fn get_by_id(&self, id: PluginId) -> Option<&dyn Plugin> {
+        self.plugins
+            .iter()
+            .find(|loaded| loaded.id == id)
+            .map(|loaded| loaded.plugin.as_ref())
+    }
+
+    /// The id of the plugin registered under `name`, for plugins registered by a library, whose
+    /// ids the application never sees otherwise.
+    pub fn id(&self, name: &str) -> Option<PluginId> {
+        self.plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)
+            .map(|loaded| loaded.id)
+    }
+
+    /// Iterates over the enabled plugins, along with the names they were registered under, in
+    /// registration order. Plugins disabled for failing too often are left out.
+    pub fn enabled_plugins(&self) -> impl Iterator<Item = (&str, &dyn Plugin)> {
+        self.plugins
+            .iter()
+            .filter(move |loaded| self.is_active(loaded))
+            .map(|loaded| (loaded.name(), loaded.plugin.as_ref()))
+    }
+
+    /// Whether the plugin named `name` is registered and enabled, and hasn't been disabled for
+    /// failing too often.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.plugins
+            .iter()
+            .any(|loaded| loaded.name() == name && self.is_active(loaded))
+    }
+
+    /// Forgets the failures of the plugin named `name`, re-enabling it if it was disabled for
+    /// failing too often. Returns whether the plugin was found.
+    pub fn reset_failures(&mut self, name: &str) -> bool {
+        match self.plugins.iter().find(|loaded| loaded.name() == name) {
+            Some(loaded) => {
+                loaded.failures.store(0, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Asks the plugin named `name` to stop what it's doing, through `HostContext::is_cancelled`.
+    /// Unlike a timeout, this relies on the plugin checking, but lets it stop cleanly. The
+    /// plugin stays cancelled until `reset_cancellation` is called. Returns whether the plugin
+    /// was found.
+    pub fn cancel(&self, name: &str) -> bool {
+        match self.plugins.iter().find(|loaded| loaded.name() == name) {
+            Some(loaded) => {
+                loaded.cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clears a previous `cancel` of the plugin named `name`. Returns whether the plugin was
+    /// found.
+    pub fn reset_cancellation(&self, name: &str) -> bool {
+        match self.plugins.iter().find(|loaded| loaded.name() == name) {
+            Some(loaded) => {
+                loaded.cancellation.reset();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_active(&self, loaded: &LoadedPlugin) -> bool {
+        loaded.enabled
+            && self
+                .failure_threshold
+                .is_none_or(|threshold| loaded.failures.load(Ordering::Relaxed) < threshold)
+    }
+
+    /// The number of registered plugins.
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    /// Whether no plugins are registered.
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Counts the registered plugins, and how many of them handle each callback.
+    pub fn summary(&self) -> PluginSummary {
+        let mut summary = PluginSummary {
+            total: self.len(),
+            handles_callback1: 0,
+            handles_callback2: 0,
+            names: self.names().map(str::to_string).collect(),
+        };
+        for plugin in self.plugins() {
+            let capabilities = plugin.capabilities();
+            if capabilities.contains(Capabilities::HANDLES_CALLBACK1) {
+                summary.handles_callback1 += 1;
+            }
+            if capabilities.contains(Capabilities::HANDLES_CALLBACK2) {
+                summary.handles_callback2 += 1;
+            }
+        }
+        summary
+    }
+
+    /// Asks every registered plugin how it's doing, in registration order.
+    pub fn health_report(&self) -> Vec<(String, Health)> {
+        self.plugins
+            .iter()
+            .map(|loaded| {
+                let health = core::call_safely(|| loaded.plugin.health())
+                    .unwrap_or_else(|err| Health::Failed(err.to_string()));
+                (loaded.name().to_string(), health)
+            })
+            .collect()
+    }
+
+    /// Iterates over the names of the registered plugins in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(LoadedPlugin::name)
+    }
+
+    /// The path of the library the plugin named `name` was loaded from, if it came from one.
+    pub fn source_path(&self, name: &str) -> Option<&Path> {
+        self.plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)?
+            .source
+            .as_deref()
+    }
+
+    /// The library the plugin named `name` was loaded from, for looking up symbols beyond the
+    /// ones the manager uses. Calling `get` on it is `unsafe`, as it's up to the caller to name
+    /// the right type for the symbol, and symbols must not be used after the plugin's library
+    /// is closed, which may happen as soon as the plugin is unloaded.
+    pub fn raw_library(&self, name: &str) -> Option<&Library> {
+        self.plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)?
+            .library
+            .as_deref()
+    }
+
+    /// Unloads the plugin named `name`, returning whether it was found. Its library stays open
+    /// as long as other plugins registered from the same library are loaded. Unloading the last
+    /// of them closes the library, along with any formatters and async plugins it registered.
+    pub fn unload(&mut self, name: &str) -> bool {
+        match self.plugins.iter().position(|loaded| loaded.name() == name) {
+            Some(index) => {
+                let loaded = self.plugins.remove(index);
+                let source = loaded.source.clone();
+                loaded.unload();
+                self.metrics.incr(metrics::PLUGINS_UNLOADED, 1);
+                if let Some(source) = source {
+                    let remaining = self
+                        .plugins
+                        .iter()
+                        .filter(|loaded| loaded.source.as_ref() == Some(&source))
+                        .count();
+                    if remaining == 0 {
+                        self.close_library(&source);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the formatters and async plugins registered by the library at `path`, and the
+    /// manager's handle on the library. Its plugins must have been unloaded already.
+    fn close_library(&mut self, path: &Path) {
+        self.formatters
+            .retain(|loaded| loaded.source.as_deref() != Some(path));
+        self.async_plugins
+            .retain(|loaded| loaded.source.as_deref() != Some(path));
+        self.libraries.retain(|library| library.path != path);
+    }
+
+    /// Unloads every plugin, and only then closes the libraries they were loaded from.
+    pub fn unload_all(&mut self) {
+        for loaded in self.plugins.drain(..) {
+            loaded.unload();
+            self.metrics.incr(metrics::PLUGINS_UNLOADED, 1);
+        }
+        self.formatters.clear();
+        self.async_plugins.clear();
+        self.libraries.clear();
+    }
+
+    /// Calls `callback1` and then `callback2` with `input` on every registered `AsyncPlugin`,
+    /// concurrently, returning each plugin's name along with its result once they've all
+    /// finished. The futures are run by whichever executor awaits this one.
+    pub async fn run_all_async(&self, input: i32) -> Vec<(&str, i32)> {
+        let calls = self.async_plugins.iter().map(|loaded| async move {
+            loaded.plugin.callback1().await;
+            let output = loaded.plugin.callback2(input).await;
+            (loaded.plugin.metadata().name, output)
+        });
+        future::join_all(calls).await
+    }
+
+    /// Iterates over the registered formatters in registration order.
+    pub fn formatters(&self) -> impl Iterator<Item = &dyn Formatter> {
+        self.formatters
+            .iter()
+            .map(|loaded| loaded.formatter.as_ref())
+    }
+
+    /// Calls `callback1` on every enabled plugin that handles it, one after the other, returning
+    /// the plugins that panicked.
+    pub fn run_all_callback1(&self) -> Vec<(&str, PluginError)> {
+        self.callback1_targets()
+            .filter_map(|loaded| {
+                self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+                let result = self.count_panic(core::call_safely(|| loaded.plugin.callback1()));
+                result.err().map(|err| (loaded.name(), err))
+            })
+            .collect()
+    }
+
+    /// Like `run_all_callback1`, but calls the plugins concurrently on rayon's thread pool. A
+    /// plugin that panics doesn't affect the others.
+    pub fn run_all_callback1_parallel(&self) -> Vec<(&str, PluginError)> {
+        self.callback1_targets()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|loaded| {
+                self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+                let result = self.count_panic(core::call_safely(|| loaded.plugin.callback1()));
+                result.err().map(|err| (loaded.name(), err))
+            })
+            .collect()
+    }
+
+    fn callback1_targets(&self) -> impl Iterator<Item = &LoadedPlugin> {
+        self.plugins.iter().filter(move |loaded| {
+            self.is_active(loaded)
+                && loaded
+                    .plugin
+                    .capabilities()
+                    .contains(Capabilities::HANDLES_CALLBACK1)
+        })
+    }
+
+    /// Calls `callback2` with `input` on every enabled plugin that handles it, in order,
+    /// returning each plugin's name along with its result. Plugins whose callback fails or
+    /// panics, or whose `schema` doesn't allow `input`, are left out, with a warning.
+    ///
+    /// The results are easy to combine, e.g. to add them up:
+    ///
+    /// ```
+    /// use core::PluginRegistrar;
+    /// use plugin_example::PluginManagerBuilder;
+    ///
+    /// let mut manager = PluginManagerBuilder::new().build();
+    /// let registrar: &mut dyn PluginRegistrar = &mut manager;
+    /// registrar.register_fn("Increment", || {}, |i| i + 1);
+    /// registrar.register_fn("Decrement", || {}, |i| i - 1);
+    ///
+    /// let total: i32 = manager.map_callback2(7).iter().map(|(_, output)| output).sum();
+    /// assert_eq!(total, 8 + 6);
+    /// ```
+    pub fn map_callback2(&self, input: i32) -> Vec<(String, i32)> {
+        self.map_callback2_where(input, |_| true)
+    }
+
+    /// Like `map_callback2`, but only calls the plugins whose `group` is `group`.
+    pub fn run_group(&self, group: &str, input: i32) -> Vec<(String, i32)> {
+        self.map_callback2_where(input, |plugin| plugin.group() == Some(group))
+    }
+
+    fn map_callback2_where(
+        &self,
+        input: i32,
+        filter: impl Fn(&dyn Plugin) -> bool,
+    ) -> Vec<(String, i32)> {
+        self.try_callback2_where(input, filter)
+            .into_iter()
+            .filter_map(|(name, result)| result.ok().map(|output| (name, output)))
+            .collect()
+    }
+
+    /// Calls `callback2` on the plugins `map_callback2_where` would, keeping the errors of those
+    /// that fail, which are also logged.
+    fn try_callback2_where(
+        &self,
+        input: i32,
+        filter: impl Fn(&dyn Plugin) -> bool,
+    ) -> Vec<(String, Result<i32, PluginError>)> {
+        self.plugins
+            .iter()
+            .filter(|loaded| self.is_active(loaded))
+            .filter(|loaded| filter(loaded.plugin.as_ref()))
+            .filter(|loaded| {
+                loaded
+                    .plugin
+                    .capabilities()
+                    .contains(Capabilities::HANDLES_CALLBACK2)
+            })
+            .filter(
+                |loaded| match loaded.plugin.schema().callback2_input.check(input.into()) {
+                    Ok(()) => true,
+                    Err(message) => {
+                        log::warn!(
+                            "skipping {}: {}",
+                            loaded.name(),
+                            PluginError::InvalidInput(message)
+                        );
+                        false
+                    }
+                },
+            )
+            .map(|loaded| {
+                self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+                let result = self
+                    .count_panic(core::call_safely(|| loaded.plugin.callback2(input)))
+                    .and_then(|result| result.map_err(PluginError::Callback));
+                self.record_result(loaded, &result);
+                if let Err(err) = &result {
+                    log::warn!("{} failed: {}", loaded.name(), err);
+                }
+                (loaded.name().to_string(), result)
+            })
+            .collect()
+    }
+
+    /// Counts a failed callback towards the failure threshold, or resets the count after one
+    /// that succeeded.
+    fn record_result<T>(&self, loaded: &LoadedPlugin, result: &Result<T, PluginError>) {
+        match result {
+            Ok(_) => loaded.failures.store(0, Ordering::Relaxed),
+            Err(err) => {
+                let failures = loaded.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if self.failure_threshold == Some(failures) {
+                    log::warn!(
+                        "disabling {} after {} failures in a row, most recently: {}",
+                        loaded.name(),
+                        failures,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// Like `map_callback2`, but returns the results as a JSON array of
+    /// `{ "name": ..., "callback2": ... }` objects, for machine-readable output. Plugins whose
+    /// callback fails are included as `{ "name": ..., "error": ... }` instead.
+    pub fn run_and_serialize(&self, input: i32) -> String {
+        #[derive(Serialize)]
+        struct Output<'a> {
+            name: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            callback2: Option<i32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            error: Option<String>,
+        }
+
+        let results = self.try_callback2_where(input, |_| true);
+        let outputs: Vec<Output> = results
+            .iter()
+            .map(|(name, result)| Output {
+                name,
+                callback2: result.as_ref().ok().copied(),
+                error: result.as_ref().err().map(PluginError::to_string),
+            })
+            .collect();
+        serde_json::to_string(&outputs).expect("plugin results are always serializable")
+    }
+
+    /// Calls `callback2` on the plugin named `name`, giving up after the timeout the manager was
+    /// built with, if any. If the manager was built with a failure threshold, a plugin whose
+    /// callback fails that many times in a row, here or in `map_callback2`, is disabled until
+    /// `reset_failures` is called. Input the plugin's `schema` doesn't allow is refused with
+    /// `PluginError::InvalidInput`, and a disabled plugin with `PluginError::Disabled`, without
+    /// calling the plugin.
+    pub fn call_callback2(&self, name: &str, i: i32) -> Result<i32, PluginError> {
+        let loaded = self
+            .plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+        if !self.is_active(loaded) {
+            return Err(PluginError::Disabled(name.to_string()));
+        }
+        loaded
+            .plugin
+            .schema()
+            .callback2_input
+            .check(i.into())
+            .map_err(PluginError::InvalidInput)?;
+        let result = match self.timeout {
+            Some(timeout) => self.call_on_helper_thread(loaded, i, timeout),
+            None => {
+                self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+                self.count_panic(guard(self.panic_isolation, || loaded.plugin.callback2(i)))
+                    .and_then(|result| result.map_err(PluginError::Callback))
+            }
+        };
+        self.record_result(loaded, &result);
+        result
+    }
+
+    /// Runs the command called `command` on the plugin named `plugin`, returning its output.
+    /// Commands the plugin doesn't list in its `commands` are refused with
+    /// `PluginError::UnknownCommand`, and a command that fails is reported as
+    /// `PluginError::Callback`. Like `call_callback2`, this refuses disabled plugins.
+    pub fn run_command(
+        &self,
+        plugin: &str,
+        command: &str,
+        args: &[String],
+    ) -> Result<String, PluginError> {
+        let loaded = self
+            .plugins
+            .iter()
+            .find(|loaded| loaded.name() == plugin)
+            .ok_or_else(|| PluginError::NotFound(plugin.to_string()))?;
+        if !self.is_active(loaded) {
+            return Err(PluginError::Disabled(plugin.to_string()));
+        }
+        if !loaded.plugin.commands().contains(&command) {
+            return Err(PluginError::UnknownCommand {
+                plugin: plugin.to_string(),
+                command: command.to_string(),
+            });
+        }
+        self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+        self.count_panic(guard(self.panic_isolation, || {
+            loaded.plugin.run_command(command, args)
+        }))?
+        .map_err(PluginError::Callback)
+    }
+
+    /// Calls `callback2` on the plugin named `name` from a helper thread, giving up with
+    /// `PluginError::Timeout` if it doesn't return within `timeout`. Disabled plugins are refused,
+    /// and failures count towards the failure threshold, as they do in `call_callback2`.
+    ///
+    /// A callback that never returns can't be stopped, so its thread is leaked, along with the
+    /// plugin and its library: neither is dropped until the callback returns, even if the
+    /// plugin is unloaded in the meantime. If that happens, `on_unload` is skipped.
+    pub fn call_with_timeout(
+        &self,
+        name: &str,
+        i: i32,
+        timeout: Duration,
+    ) -> Result<i32, PluginError> {
+        let loaded = self
+            .plugins
+            .iter()
+            .find(|loaded| loaded.name() == name)
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+        if !self.is_active(loaded) {
+            return Err(PluginError::Disabled(name.to_string()));
+        }
+        let result = self.call_on_helper_thread(loaded, i, timeout);
+        self.record_result(loaded, &result);
+        result
+    }
+
+    fn call_on_helper_thread(
+        &self,
+        loaded: &LoadedPlugin,
+        i: i32,
+        timeout: Duration,
+    ) -> Result<i32, PluginError> {
+        let plugin = loaded.plugin.clone();
+        let library = loaded.library.clone();
+
+        self.metrics.incr(metrics::CALLBACK_INVOCATIONS, 1);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = core::call_safely(|| plugin.callback2(i));
+            // The plugin's code lives in the library, so drop it first.
+            drop(plugin);
+            drop(library);
+            let _ = sender.send(result);
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(Ok(Ok(value))) => Ok(value),
+            Ok(Ok(Err(message))) => Err(PluginError::Callback(message)),
+            Ok(Err(err)) => self.count_panic(Err(err)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(PluginError::Timeout(timeout)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(PluginError::Panicked(
+                "callback thread exited without a result".to_string(),
+            )),
+        }
+    }
+}
+
+/// How `PluginManager::resolve_dependencies_by` orders plugins that don't depend on each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By the path of the library they came from, and then in registration order. Plugins
+    /// that weren't loaded from a library come first.
+    #[default]
+    Name,
+    /// By priority, lowest first, and then in registration order.
+    Priority,
+}
+
+/// What happened to each library given to `PluginManager::load_all`, or found by `load_dir` or
+/// `load_manifest`.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    /// The names of the plugins that were registered, in registration order
+    pub loaded: Vec<String>,
+    /// The libraries that failed to load, and why. A library can fail after registering some
+    /// of its plugins, if others were rejected.
+    pub failed: Vec<(PathBuf, PluginError)>,
+    /// The libraries that were skipped because they were already loaded, even if through a
+    /// different path
+    pub skipped: Vec<PathBuf>,
+    /// The names of the plugins the `on_register` hook refused to register
+    pub vetoed: Vec<String>,
+    /// How long each library took to load, in the order they were given
+    pub timings: Vec<(PathBuf, Duration)>,
+}
+
+impl LoadReport {
+    /// Adds what's in `other` to the end of this report, e.g. to report on libraries loaded
+    /// from a directory and a manifest together.
+    pub fn append(&mut self, mut other: LoadReport) {
+        self.loaded.append(&mut other.loaded);
+        self.failed.append(&mut other.failed);
+        self.skipped.append(&mut other.skipped);
+        self.vetoed.append(&mut other.vetoed);
+        self.timings.append(&mut other.timings);
+    }
+}
+
+/// Aggregate counts over the registered plugins, as returned by `PluginManager::summary`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginSummary {
+    /// How many plugins are registered
+    pub total: usize,
+    /// How many plugins handle `callback1`
+    pub handles_callback1: usize,
+    /// How many plugins handle `callback2`
+    pub handles_callback2: usize,
+    /// The names of the registered plugins in registration order
+    pub names: Vec<String>,
+}
+
+/// How many times `reload_library` tries to open a library that was just rewritten.
+const RELOAD_ATTEMPTS: usize = 3;
+/// How long to wait for a rewritten library to settle before loading it.
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// A registered plugin, along with its id, the name it was registered under and the library it
+/// came from. The plugin is shared with any `call_with_timeout` threads that are still running
+/// its callbacks.
+///
+/// Each plugin holds a reference to its library, so the library stays open for as long as any
+/// of its plugins are alive, whoever drops them.
+struct LoadedPlugin {
+    plugin: Arc<dyn Plugin>,
+    id: PluginId,
+    name: String,
+    enabled: bool,
+    /// How many times in a row `call_callback2` has failed
+    failures: AtomicU32,
+    /// Set by `PluginManager::cancel`
+    cancellation: CancellationToken,
+    source: Option<PathBuf>,
+    /// Declared last, so the plugin is dropped before its library
+    library: Option<Arc<Library>>,
+}
+
+impl LoadedPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls `on_unload` and drops the plugin, unless a timed out callback is still using it.
+    fn unload(mut self) {
+        match Arc::get_mut(&mut self.plugin) {
+            Some(plugin) => plugin.on_unload(),
+            None => log::warn!(
+                "{} is still running a timed out callback, skipping `on_unload`",
+                self.name()
+            ),
+        }
+    }
+}
+
+/// A registered formatter, along with the path of the library it came from.
+struct LoadedFormatter {
+    formatter: Box<dyn Formatter>,
+    source: Option<PathBuf>,
+}
+
+/// A registered async plugin, along with the path of the library it came from.
+struct LoadedAsyncPlugin {
+    plugin: Box<dyn AsyncPlugin>,
+    source: Option<PathBuf>,
+}
+
+/// An open plugin library. Dropping it closes the library, so the plugins it registered must be
+/// unloaded first. Any event handlers it subscribed are removed before it's closed. The library
+/// is shared with any `call_with_timeout` threads that are still running its code, and is only
+/// closed once they're done.
+struct LoadedLibrary {
+    path: PathBuf,
+    id: u64,
+    events: Arc<LocalEventBus>,
+    library: Arc<Library>,
+    /// The command-line arguments its plugins were given, kept for when it's reloaded
+    args: Vec<String>,
+    /// The temporary file the library was written to by `load_from_bytes`, deleted once the
+    /// library is unloaded
+    temp: Option<TempPath>,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        self.events.unsubscribe_owner(self.id);
+    }
+}
+
+/// The plugin libraries being watched for `PluginManager::poll_reloads`.
+struct Watch {
+    /// Stops watching once dropped
+    _watcher: notify::RecommendedWatcher,
+    /// Behind a mutex so the manager stays `Sync`
+    events: Mutex<mpsc::Receiver<notify::Result<notify::Event>>>,
+    paths: Vec<PathBuf>,
+}
+
+impl Watch {
+    /// The watched libraries that have been rewritten since this was last called.
+    fn changed(&self) -> Vec<PathBuf> {
+        let events = self.events.lock().unwrap();
+        let mut changed: Vec<PathBuf> = Vec::new();
+        let mut event = events.try_recv().ok();
+        while let Some(next) = event {
+            match next {
+                Ok(next) if next.kind.is_create() || next.kind.is_modify() => {
+                    for path in &self.paths {
+                        if next.paths.contains(path) && !changed.contains(path) {
+                            changed.push(path.clone());
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("error watching plugin libraries: {}", err),
+            }
+            // A single rebuild produces a burst of events, so once one arrives, wait for the
+            // rest to settle and handle them together.
+            event = events.recv_timeout(RELOAD_DELAY).ok();
+        }
+        changed
+    }
+}
+
+/// Runs `f`, catching any panic if `isolate` is set.
+fn guard<R>(isolate: bool, f: impl FnOnce() -> R) -> Result<R, PluginError> {
+    if isolate {
+        core::call_safely(f)
+    } else {
+        Ok(f())
+    }
+}
+
+/// The name a plugin is registered under when another version of it is already loaded.
+fn versioned_name(name: &str, version: &str) -> String {
+    format!("{}@{}", name, version)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn watch_error(err: notify::Error) -> PluginError {
+    PluginError::Io(err.to_string())
+}
+
+impl PluginManager {
+    /// The context handed to code from the library `library`, or from the application itself
+    /// when `None`.
+    fn context_for(&self, library: Option<u64>) -> HostContext {
+        self.context
+            .clone()
+            .with_events(self.events.scoped(library))
+    }
+
+    /// Counts `result` towards `metrics::PANICS_CAUGHT` if it's a caught panic.
+    fn count_panic<T>(&self, result: Result<T, PluginError>) -> Result<T, PluginError> {
+        if let Err(PluginError::Panicked(_)) = &result {
+            self.metrics.incr(metrics::PANICS_CAUGHT, 1);
+        }
+        result
+    }
+
+    /// Registers `plugin` under its instance name, recording the library it came from, and
+    /// calls its `on_load`. Plugins with a duplicate name, or whose `on_load` fails, are dropped
+    /// and the error is reported by the loader. Plugins vetoed by the `on_register` hook are
+    /// dropped before their `on_load` is called. Either way, the plugin is given a new id.
+    fn register_from(
+        &mut self,
+        mut plugin: Box<dyn Plugin>,
+        config: PluginConfig,
+        source: Option<(&Path, &Arc<Library>)>,
+        context: &HostContext,
+    ) -> PluginId {
+        let id = PluginId::new(self.next_plugin_id);
+        self.next_plugin_id += 1;
+        let metadata = plugin.metadata();
+        let named = config.instance_name.is_some();
+        let mut name = config
+            .instance_name
+            .unwrap_or_else(|| metadata.name.to_string());
+        if let Some(existing) = self.get(&name) {
+            // Another version of the same plugin gets a name of its own, until
+            // `dedup_by_name_keeping_newest` picks one.
+            if !named && existing.metadata().version != metadata.version {
+                name = versioned_name(metadata.name, metadata.version);
+            }
+        }
+        if self.get(&name).is_some() {
+            self.rejected.push(PluginError::DuplicateName(name));
+            return id;
+        }
+        if let Some(on_register) = &self.on_register {
+            if !on_register(&*plugin) {
+                log::info!("not registering {}: vetoed by the registration hook", name);
+                self.vetoed.push(name);
+                return id;
+            }
+        }
+        let cancellation = CancellationToken::new();
+        let context = context
+            .clone()
+            .with_plugin_name(&name)
+            .with_state(self.state.scoped(&name))
+            .with_cancellation(cancellation.clone());
+        let loaded = guard(self.panic_isolation, || plugin.on_load(&context));
+        if let Err(err) = loaded.and_then(|result| result) {
+            self.rejected.push(err);
+            return id;
+        }
+        self.plugins.push(LoadedPlugin {
+            plugin: plugin.into(),
+            id,
+            name,
+            enabled: config.enabled,
+            failures: AtomicU32::new(0),
+            cancellation,
+            source: source.map(|(path, _)| path.to_path_buf()),
+            library: source.map(|(_, library)| library.clone()),
+        });
+        self.metrics.incr(metrics::PLUGINS_LOADED, 1);
+        id
+    }
+}
+
+impl PluginRegistrar for PluginManager {
+    fn register_plugin_with_config(
+        &mut self,
+        plugin: Box<dyn Plugin>,
+        config: PluginConfig,
+    ) -> PluginId {
+        let context = self.context_for(None);
+        self.register_from(plugin, config, None, &context)
+    }
+
+    fn register_formatter(&mut self, formatter: Box<dyn Formatter>) {
+        self.formatters.push(LoadedFormatter {
+            formatter,
+            source: None,
+        });
+    }
+
+    fn register_async_plugin(&mut self, plugin: Box<dyn AsyncPlugin>) {
+        self.async_plugins.push(LoadedAsyncPlugin {
+            plugin,
+            source: None,
+        });
+    }
+}
+
+/// The registrar handed to `plugin_entry` while loading the library at `source`.
+struct Registrar<'a> {
+    manager: &'a mut PluginManager,
+    source: &'a Path,
+    library: &'a Arc<Library>,
+    context: &'a HostContext,
+}
+
+impl PluginRegistrar for Registrar<'_> {
+    fn register_plugin_with_config(
+        &mut self,
+        plugin: Box<dyn Plugin>,
+        config: PluginConfig,
+    ) -> PluginId {
+        self.manager.register_from(
+            plugin,
+            config,
+            Some((self.source, self.library)),
+            self.context,
+        )
+    }
+
+    fn register_formatter(&mut self, formatter: Box<dyn Formatter>) {
+        self.manager.formatters.push(LoadedFormatter {
+            formatter,
+            source: Some(self.source.to_path_buf()),
+        });
+    }
+
+    fn register_async_plugin(&mut self, plugin: Box<dyn AsyncPlugin>) {
+        self.manager.async_plugins.push(LoadedAsyncPlugin {
+            plugin,
+            source: Some(self.source.to_path_buf()),
+        });
+    }
+}
+
+impl Drop for PluginManager {
+    fn drop(&mut self) {
+        self.unload_all();
+    }
+}
+
+/// A `HostLogger` that forwards plugin messages to the `log` crate, with the plugin's name as
+/// the target. Whichever logger the application installs decides what's shown.
+pub struct ForwardingLogger;
+
+impl HostLogger for ForwardingLogger {
+    fn log(&self, level: LogLevel, target: &str, message: &str) {
+        let level = match level {
+            LogLevel::Error => log::Level::Error,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Trace => log::Level::Trace,
+        };
+        log::log!(target: target, level, "{}", message);
+    }
+}
+
+/// A `ProgressSink` that logs each update through the `log` crate at the info level, with the
+/// plugin's name as the target.
+pub struct ProgressLogger;
+
+impl ProgressSink for ProgressLogger {
+    fn report(&self, plugin: &str, fraction: f32, message: &str) {
+        log::info!(target: plugin, "{:.0}% {}", fraction * 100.0, message);
+    }
+}
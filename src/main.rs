@@ -1,37 +1,225 @@
-use libloading::Library;
+use std::io;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
-use core::{Plugin, PluginRegistrar};
+use core::{Capabilities, PluginError};
+use plugin_example::args::{self, Flags};
+use plugin_example::{repl, LoadReport, PluginManagerBuilder};
 
-struct Registrar {
-    plugins: Vec<Box<dyn Plugin>>,
-}
+/// How long a plugin's `callback2` may run before the application gives up on it.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format_timestamp(None)
+        .init();
 
-impl PluginRegistrar for Registrar {
-    fn register_plugin(&mut self, plugin: Box<dyn Plugin>) {
-        self.plugins.push(plugin);
+    let (paths, flags) = args::parse(std::env::args_os().skip(1));
+    let Flags {
+        dirs,
+        manifest,
+        config,
+        check,
+        repl,
+        strict,
+        group,
+        verbose,
+        sequential,
+        json,
+        allow,
+        allow_multiple_versions,
+        sort_by,
+    } = flags;
+
+    let mut builder = PluginManagerBuilder::new()
+        .with_config(Arc::new(config))
+        .with_panic_isolation(true)
+        .with_timeout(CALLBACK_TIMEOUT);
+    if let Some(allow) = allow {
+        builder = builder.on_register(move |plugin| {
+            let name = plugin.metadata().name;
+            let allowed = allow.iter().any(|allowed| allowed == name);
+            if !allowed {
+                log::debug!("{} isn't in `--allow`", name);
+            }
+            allowed
+        });
+    }
+    let mut manager = builder.build();
+    let mut report = LoadReport::default();
+    if let Some(manifest) = &manifest {
+        match manager.load_manifest(manifest) {
+            Ok(manifest_report) => report.append(manifest_report),
+            Err(err) => {
+                log::error!("{}: {}", manifest.display(), err);
+                process::exit(1);
+            }
+        }
     }
-}
 
-fn main() {
-    let mut registrar = Registrar {
-        plugins: Vec::new(),
+    #[cfg(feature = "static-plugins")]
+    manager.register_builtin_plugins();
+
+    let mut failed = false;
+    for dir in &dirs {
+        match manager.load_dir(dir) {
+            Ok(dir_report) => report.append(dir_report),
+            Err(err) => {
+                log::warn!("skipping {}: {}", dir.display(), err);
+                failed = true;
+            }
+        }
+    }
+    report.append(manager.load_all_with_args(&paths));
+    for (path, time) in &report.timings {
+        let err = report
+            .failed
+            .iter()
+            .find(|(failed, _)| failed == path)
+            .map(|(_, err)| err);
+        let status = if err.is_some() {
+            "failed"
+        } else if report.skipped.contains(path) {
+            "skipped"
+        } else {
+            "loaded"
+        };
+        eprint!("{:<7} {:>8.1?}  {}", status, time, path.display());
+        match err {
+            Some(err) => eprintln!(": {}", err),
+            None => eprintln!(),
+        }
+    }
+    if strict && (failed || !report.failed.is_empty()) {
+        // Exiting skips destructors, so unload the plugins first.
+        drop(manager);
+        process::exit(1);
+    }
+
+    if !allow_multiple_versions {
+        for name in manager.dedup_by_name_keeping_newest() {
+            log::info!("unloaded {}, a newer version is loaded", name);
+        }
+    }
+    for err in manager.resolve_dependencies_by(sort_by) {
+        log::warn!("{}", err);
+        failed = true;
+    }
+
+    if check {
+        for (name, plugin) in manager.names().zip(manager.plugins()) {
+            let metadata = plugin.metadata();
+            print!("ok: {} v{}", name, metadata.version);
+            if let Some(source) = manager.source_path(name) {
+                print!(" ({})", source.display());
+            }
+            if !manager.is_enabled(name) {
+                print!(" [disabled]");
+            }
+            println!();
+            if let Some(description) = metadata.description {
+                println!("    {}", description);
+            }
+            let capabilities = plugin.capabilities();
+            println!(
+                "    callback1: {}, callback2: {}",
+                capabilities.contains(Capabilities::HANDLES_CALLBACK1),
+                capabilities.contains(Capabilities::HANDLES_CALLBACK2)
+            );
+            if capabilities.contains(Capabilities::HANDLES_CALLBACK2) {
+                let schema = plugin.schema();
+                println!(
+                    "    callback2 takes {} and returns {}",
+                    schema.callback2_input, schema.callback2_output
+                );
+            }
+        }
+        for path in manager.failed_paths() {
+            println!("failed: {}", path.display());
+        }
+        failed |= !manager.failed_paths().is_empty();
+        // Exiting skips destructors, so unload the plugins first.
+        drop(manager);
+        process::exit(if failed { 1 } else { 0 });
+    }
+
+    if verbose {
+        let summary = manager.summary();
+        println!(
+            "{} plugins loaded ({} handle callback1, {} handle callback2): {}",
+            summary.total,
+            summary.handles_callback1,
+            summary.handles_callback2,
+            summary.names.join(", ")
+        );
+    }
+
+    if repl {
+        repl::run(&mut manager, io::stdin().lock(), io::stdout());
+        return;
+    }
+
+    if let Some(group) = &group {
+        for (name, result) in manager.run_group(group, 7) {
+            println!("{}: {}", name, result);
+        }
+        return;
+    }
+
+    let failures = if sequential {
+        manager.run_all_callback1()
+    } else {
+        manager.run_all_callback1_parallel()
     };
+    for (name, err) in &failures {
+        log::warn!("{} failed: {}", name, err);
+    }
 
-    for path in std::env::args_os().skip(1) {
-        // In this code, we never close the shared library - if you need to be able to unload the
-        // library, that will require more work.
-        let lib = Box::leak(Box::new(Library::new(path).unwrap()));
-        // NOTE: You need to do something to ensure you're only loading "safe" code. Out of scope
-        // for this code.
-        unsafe {
-            let func: libloading::Symbol<unsafe extern "C" fn(&mut dyn PluginRegistrar) -> ()> =
-                lib.get(b"plugin_entry").unwrap();
-            func(&mut registrar);
+    if json {
+        println!("{}", manager.run_and_serialize(7));
+        return;
+    }
+
+    for (name, plugin) in manager.enabled_plugins() {
+        if failures.iter().any(|(failed, _)| *failed == name) {
+            continue;
+        }
+        println!("{} v{}", name, plugin.metadata().version);
+        if !plugin
+            .capabilities()
+            .contains(Capabilities::HANDLES_CALLBACK2)
+        {
+            continue;
+        }
+        match manager.call_callback2(name, 7) {
+            Ok(result) => {
+                dbg!(result);
+            }
+            Err(PluginError::Callback(err)) => {
+                println!("{}::callback2 returned an error: {}", name, err)
+            }
+            Err(err) => log::warn!("{} failed: {}", name, err),
+        }
+    }
+
+    for (name, result) in futures::executor::block_on(manager.run_all_async(7)) {
+        println!("{} (async)", name);
+        dbg!(result);
+    }
+
+    for formatter in manager.formatters() {
+        match core::call_safely(|| formatter.format("all plugins ran")) {
+            Ok(formatted) => println!("{}", formatted),
+            Err(err) => log::warn!("formatter failed: {}", err),
         }
     }
 
-    for plugin in registrar.plugins {
-        plugin.callback1();
-        dbg!(plugin.callback2(7));
+    if verbose {
+        for (name, health) in manager.health_report() {
+            if !health.is_ok() {
+                println!("{} is {}", name, health);
+            }
+        }
     }
 }
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use core::{HostContext, PluginError};
+
+use crate::{LoadReport, PluginManager};
+
+/// A `plugins.toml` manifest listing the plugin libraries to load, in order:
+///
+/// ```toml
+/// [[plugin]]
+/// path = "target/debug/libplugin_a.so"
+///
+/// [[plugin]]
+/// path = "target/debug/libplugin_b.so"
+/// enabled = false
+/// ```
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    plugin: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    path: PathBuf,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl PluginManager {
+    /// Creates a manager and loads every enabled plugin listed in the manifest at `path`. See
+    /// `load_manifest`.
+    pub fn load_from_manifest(
+        path: &Path,
+        context: HostContext,
+    ) -> Result<PluginManager, PluginError> {
+        let mut manager = PluginManager::new(context);
+        manager.load_manifest(path)?;
+        Ok(manager)
+    }
+
+    /// Loads every enabled plugin listed in the manifest at `path`, in the order they're
+    /// listed. Relative plugin paths are resolved against the manifest's directory. Unknown
+    /// keys are ignored, but every entry must have a `path`. Libraries that fail to load are
+    /// skipped, and listed in the returned report's `failed`.
+    pub fn load_manifest(&mut self, path: &Path) -> Result<LoadReport, PluginError> {
+        let contents = fs::read_to_string(path).map_err(|err| PluginError::Io(err.to_string()))?;
+        let manifest: Manifest =
+            toml::from_str(&contents).map_err(|err| PluginError::Manifest(err.to_string()))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let paths: Vec<PathBuf> = manifest
+            .plugin
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| base.join(&entry.path))
+            .collect();
+        Ok(self.load_all(&paths))
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How many plugins have been registered.
+pub const PLUGINS_LOADED: &str = "plugins_loaded";
+/// How many plugins have been unloaded.
+pub const PLUGINS_UNLOADED: &str = "plugins_unloaded";
+/// How many plugin libraries failed to load.
+pub const LOADS_FAILED: &str = "loads_failed";
+/// How many times a plugin's `callback1` or `callback2` has been called.
+pub const CALLBACK_INVOCATIONS: &str = "callback_invocations";
+/// How many panics raised by plugins have been caught.
+pub const PANICS_CAUGHT: &str = "panics_caught";
+
+/// Receives the counters a `PluginManager` keeps as it loads and calls plugins. The metrics it
+/// reports are named by the constants in this module.
+pub trait MetricsSink: Send + Sync {
+    /// Adds `by` to the counter named `metric`.
+    fn incr(&self, metric: &str, by: u64);
+}
+
+/// The `MetricsSink` used when the application doesn't provide one.
+pub(crate) struct NoMetrics;
+
+impl MetricsSink for NoMetrics {
+    fn incr(&self, _metric: &str, _by: u64) {}
+}
+
+/// A `MetricsSink` that keeps the counters in memory.
+#[derive(Default)]
+pub struct CountingSink {
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+impl CountingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current value of every counter that's been incremented.
+    pub fn snapshot(&self) -> HashMap<String, u64> {
+        self.counters.lock().unwrap().clone()
+    }
+}
+
+impl MetricsSink for CountingSink {
+    fn incr(&self, metric: &str, by: u64) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(metric.to_string())
+            .or_insert(0) += by;
+    }
+}
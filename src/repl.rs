@@ -0,0 +1,87 @@
+use std::io::{self, BufRead, Write};
+
+use crate::PluginManager;
+
+/// Reads commands from `input` until it's exhausted or told to `quit`, writing a prompt before
+/// each and their results to `output`.
+pub fn run(manager: &mut PluginManager, mut input: impl BufRead, mut output: impl Write) {
+    loop {
+        if let Err(err) = write!(output, "> ").and_then(|()| output.flush()) {
+            log::error!("could not write the prompt: {}", err);
+            break;
+        }
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                log::error!("could not read a command: {}", err);
+                break;
+            }
+        }
+        match run_command(manager, &line, &mut output) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => {
+                log::error!("could not write a command's result: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+/// Runs a single command, writing its result to `output`, and returns whether to keep reading
+/// commands. Mistakes, like an unknown plugin, are reported without ending the REPL.
+pub fn run_command(
+    manager: &mut PluginManager,
+    line: &str,
+    output: &mut impl Write,
+) -> io::Result<bool> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [] => {}
+        ["list"] => {
+            for (name, plugin) in manager.names().zip(manager.plugins()) {
+                write!(output, "{}", name)?;
+                let commands = plugin.commands();
+                if !commands.is_empty() {
+                    write!(output, " (commands: {})", commands.join(", "))?;
+                }
+                if !manager.is_enabled(name) {
+                    write!(output, " [disabled]")?;
+                }
+                writeln!(output)?;
+            }
+        }
+        ["call", name, input] if input.parse::<i32>().is_ok() => {
+            match manager.call_callback2(name, input.parse().unwrap()) {
+                Ok(result) => writeln!(output, "{}", result)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            }
+        }
+        ["call", name, command, args @ ..] => {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            match manager.run_command(name, command, &args) {
+                Ok(result) => writeln!(output, "{}", result)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            }
+        }
+        ["unload", name] => {
+            if !manager.unload(name) {
+                writeln!(output, "error: no plugin named `{}` is registered", name)?;
+            }
+        }
+        ["reload", name] => {
+            if let Err(err) = manager.reload(name) {
+                writeln!(output, "error: {}", err)?;
+            }
+        }
+        ["quit"] | ["exit"] => return Ok(false),
+        _ => writeln!(
+            output,
+            "commands: list, call <name> <int>, call <name> <command> <args...>, \
+             unload <name>, reload <name>, quit"
+        )?,
+    }
+    Ok(true)
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use core::PluginStateStore;
+
+/// The state store shared by every plugin a `PluginManager` loads. Values are keyed by the name
+/// of the plugin that set them, and outlive the plugin itself, so a plugin that's reloaded can
+/// pick up where it left off.
+#[derive(Default)]
+pub(crate) struct LocalStateStore {
+    values: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl LocalStateStore {
+    /// Returns a view of the store for the plugin named `plugin`.
+    pub(crate) fn scoped(self: &Arc<Self>, plugin: &str) -> Arc<dyn PluginStateStore> {
+        Arc::new(ScopedStateStore {
+            store: self.clone(),
+            plugin: plugin.to_string(),
+        })
+    }
+}
+
+struct ScopedStateStore {
+    store: Arc<LocalStateStore>,
+    plugin: String,
+}
+
+impl PluginStateStore for ScopedStateStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.store
+            .values
+            .lock()
+            .unwrap()
+            .get(&(self.plugin.clone(), key.to_string()))
+            .cloned()
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>) {
+        self.store
+            .values
+            .lock()
+            .unwrap()
+            .insert((self.plugin.clone(), key.to_string()), value);
+    }
+}
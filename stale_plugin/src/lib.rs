@@ -0,0 +1,16 @@
+// A library that claims to be built against an older version of `core`, so the tests can check
+// the application refuses to load it.
+
+#[no_mangle]
+pub static PLUGIN_ABI_VERSION: u32 = core::ABI_VERSION - 1;
+
+#[no_mangle]
+pub static RUSTC_VERSION: &str = core::RUSTC_VERSION;
+
+#[no_mangle]
+pub static PLUGIN_ENTRY_SIGNATURE: u64 = core::ENTRY_SIGNATURE_HASH;
+
+#[no_mangle]
+pub fn plugin_entry(_registrar: &mut dyn core::PluginRegistrar, _ctx: &core::HostContext) {
+    panic!("the application called `plugin_entry` despite the ABI mismatch");
+}
@@ -0,0 +1,65 @@
+// A plugin for the tests to load, which reports on itself in ways the example plugins don't.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long `Probe::callback1` waits to be cancelled before giving up.
+const CANCEL_WAIT: Duration = Duration::from_secs(5);
+
+/// Logs `dropped` when it's dropped, so the tests can check its library was still loaded then.
+/// Its `callback1` logs `waiting` and then waits to be cancelled, logging `cancelled` once it is.
+struct Probe {
+    ctx: Option<core::HostContext>,
+}
+
+impl core::Plugin for Probe {
+    fn metadata(&self) -> core::PluginMetadata {
+        core::PluginMetadata {
+            name: "Probe",
+            version: env!("CARGO_PKG_VERSION"),
+            description: Some("Reports on itself for the tests"),
+        }
+    }
+
+    fn on_load(&mut self, ctx: &core::HostContext) -> Result<(), core::PluginError> {
+        self.ctx = Some(ctx.clone());
+        Ok(())
+    }
+
+    fn callback1(&self) {
+        let ctx = match &self.ctx {
+            Some(ctx) => ctx,
+            None => return,
+        };
+        ctx.log().info("waiting");
+        let start = Instant::now();
+        while start.elapsed() < CANCEL_WAIT {
+            if ctx.is_cancelled() {
+                ctx.log().info("cancelled");
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        if let Some(ctx) = &self.ctx {
+            ctx.log().info("dropped");
+        }
+    }
+}
+
+/// A symbol the application knows nothing about, for the tests to look up through
+/// `PluginManager::raw_library`.
+#[no_mangle]
+pub extern "C" fn extra_symbol() -> u32 {
+    42
+}
+
+fn register(registrar: &mut dyn core::PluginRegistrar, _ctx: &core::HostContext) {
+    registrar.register_plugin(Box::new(Probe { ctx: None }));
+}
+
+core::export_plugin!(register);
@@ -0,0 +1,66 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use plugin_example::args::{self, PluginPaths};
+
+fn parse(args: &[&str]) -> (PluginPaths, args::Flags) {
+    args::parse(args.iter().map(OsString::from))
+}
+
+fn path(path: &str, args: &[&str]) -> (PathBuf, Vec<String>) {
+    (
+        PathBuf::from(path),
+        args.iter().map(|arg| arg.to_string()).collect(),
+    )
+}
+
+#[test]
+fn gives_each_library_its_own_arguments() {
+    let (paths, flags) = parse(&[
+        "a.so", "--", "--foo=1", "-v", "b.so", "--", "--bar=2", "c.so", "--json",
+    ]);
+    assert_eq!(
+        paths,
+        [
+            path("a.so", &["--foo=1", "-v"]),
+            path("b.so", &["--bar=2"]),
+            path("c.so", &[]),
+        ]
+    );
+    assert!(flags.json);
+}
+
+#[test]
+fn accepts_a_trailing_separator() {
+    let (paths, _) = parse(&["a.so", "--"]);
+    assert_eq!(paths, [path("a.so", &[])]);
+}
+
+#[test]
+fn ignores_a_separator_before_any_path() {
+    let (paths, flags) = parse(&["--", "--foo=1", "a.so"]);
+    assert_eq!(paths, [path("a.so", &[])]);
+    assert_eq!(flags, args::Flags::default());
+}
+
+#[test]
+fn parses_options_with_values() {
+    let (paths, flags) = parse(&[
+        "--set",
+        "increment=2",
+        "--allow",
+        "PluginA,PluginB",
+        "--plugin-dir",
+        "plugins",
+        "--sort-by",
+        "priority",
+    ]);
+    assert!(paths.is_empty());
+    assert_eq!(flags.config["increment"], "2");
+    assert_eq!(
+        flags.allow,
+        Some(vec!["PluginA".to_string(), "PluginB".to_string()])
+    );
+    assert_eq!(flags.dirs, [PathBuf::from("plugins")]);
+    assert_eq!(flags.sort_by, plugin_example::SortOrder::Priority);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use core::{AsyncPlugin, PluginMetadata, PluginRegistrar};
+use plugin_example::PluginManagerBuilder;
+
+/// Records that its `callback1` was awaited, and doubles its input.
+struct Doubler {
+    called: Arc<AtomicBool>,
+}
+
+#[core::async_trait]
+impl AsyncPlugin for Doubler {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Doubler",
+            version: "1.0.0",
+            description: None,
+        }
+    }
+
+    async fn callback1(&self) {
+        tokio::task::yield_now().await;
+        self.called.store(true, Ordering::SeqCst);
+    }
+
+    async fn callback2(&self, i: i32) -> i32 {
+        tokio::task::yield_now().await;
+        i * 2
+    }
+}
+
+#[tokio::test]
+async fn awaits_both_callbacks_of_every_async_plugin() {
+    let called = Arc::new(AtomicBool::new(false));
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_b")).unwrap();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    registrar.register_async_plugin(Box::new(Doubler {
+        called: called.clone(),
+    }));
+
+    let results = manager.run_all_async(7).await;
+    assert_eq!(results, [("PluginCountdown", 6), ("Doubler", 14)]);
+    assert!(called.load(Ordering::SeqCst));
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core::{
+    CallbackSchema, Plugin, PluginError, PluginMetadata, PluginRegistrar, ProgressSink, ValueSchema,
+};
+use plugin_example::PluginManagerBuilder;
+use serde_json::{json, Value};
+
+#[test]
+fn reports_callback2_results_and_errors() {
+    let mut manager = PluginManagerBuilder::new().build();
+    common::load_examples(&mut manager);
+    assert_eq!(manager.call_callback2("PluginB", 7).unwrap(), 6);
+    assert!(matches!(
+        manager.call_callback2("PluginB", 0),
+        Err(PluginError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        manager.call_callback2("Missing", 7),
+        Err(PluginError::NotFound(_))
+    ));
+}
+
+#[test]
+fn reports_overflow_as_an_error() {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    for name in ["PluginA", "PluginDouble"].iter() {
+        assert!(matches!(
+            manager.call_callback2(name, i32::MAX),
+            Err(PluginError::Callback(_))
+        ));
+    }
+}
+
+#[test]
+fn refuses_input_outside_a_plugins_schema() {
+    let mut manager = PluginManagerBuilder::new().build();
+    common::load_examples(&mut manager);
+    let plugin_b = manager.get("PluginB").unwrap();
+    assert_eq!(
+        plugin_b.schema(),
+        CallbackSchema {
+            callback2_input: ValueSchema::I32.with_min(1),
+            callback2_output: ValueSchema::I32.with_min(0),
+        }
+    );
+
+    // PluginB is never called with the input it refuses, so it stays healthy.
+    assert!(matches!(
+        manager.call_callback2("PluginB", 0),
+        Err(PluginError::InvalidInput(_))
+    ));
+    assert_eq!(
+        manager.map_callback2(0),
+        [("PluginA".to_string(), 1), ("PluginDouble".to_string(), 0)]
+    );
+    assert!(manager.get("PluginB").unwrap().health().is_ok());
+}
+
+#[test]
+fn calls_plugins_from_several_threads_at_once() {
+    let mut manager = PluginManagerBuilder::new().build();
+    common::load_examples(&mut manager);
+    let manager = &manager;
+    let results: Vec<(String, i32)> = thread::scope(|scope| {
+        let calls: Vec<_> = manager
+            .names()
+            .map(|name| {
+                scope.spawn(move || {
+                    let output = manager.call_callback2(name, 7).unwrap();
+                    (name.to_string(), output)
+                })
+            })
+            .collect();
+        calls.into_iter().map(|call| call.join().unwrap()).collect()
+    });
+    assert_eq!(
+        results,
+        [
+            ("PluginA".to_string(), 8),
+            ("PluginDouble".to_string(), 14),
+            ("PluginB".to_string(), 6),
+        ]
+    );
+}
+
+/// Records the fractions every plugin reports.
+#[derive(Clone, Default)]
+struct Progress(Arc<Mutex<Vec<(String, f32)>>>);
+
+impl ProgressSink for Progress {
+    fn report(&self, plugin: &str, fraction: f32, _message: &str) {
+        self.0.lock().unwrap().push((plugin.to_string(), fraction));
+    }
+}
+
+#[test]
+fn reports_progress_in_increasing_order() {
+    let progress = Progress::default();
+    let mut manager = PluginManagerBuilder::new()
+        .progress_sink(Box::new(progress.clone()))
+        .build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    assert!(manager.run_all_callback1().is_empty());
+
+    let fractions: Vec<f32> = progress
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(plugin, _)| plugin == "PluginA")
+        .map(|(_, fraction)| *fraction)
+        .collect();
+    assert_eq!(fractions.first(), Some(&0.0));
+    assert_eq!(fractions.last(), Some(&1.0));
+    assert!(fractions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn serializes_results_and_errors_as_json() {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager
+        .load_plugin(&common::library("panic_plugin"))
+        .unwrap();
+
+    let json: Value = serde_json::from_str(&manager.run_and_serialize(7)).unwrap();
+    let outputs = json.as_array().unwrap();
+    assert_eq!(outputs.len(), 3);
+    assert_eq!(outputs[0], json!({ "name": "PluginA", "callback2": 8 }));
+    assert_eq!(
+        outputs[1],
+        json!({ "name": "PluginDouble", "callback2": 14 })
+    );
+    assert_eq!(outputs[2]["name"], "Panicky");
+    assert!(outputs[2]["error"].is_string());
+    assert!(outputs[2].get("callback2").is_none());
+}
+
+/// A plugin whose `callback2` always fails.
+struct Failing;
+
+impl Plugin for Failing {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Failing",
+            version: "1.0.0",
+            description: None,
+        }
+    }
+
+    fn callback2(&self, _i: i32) -> Result<i32, String> {
+        Err("always fails".to_string())
+    }
+}
+
+#[test]
+fn disables_a_plugin_that_keeps_failing() {
+    let mut manager = PluginManagerBuilder::new()
+        .with_failure_threshold(2)
+        .build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    registrar.register_plugin(Box::new(Failing));
+    let timeout = Duration::from_secs(5);
+
+    for _ in 0..2 {
+        assert!(matches!(
+            manager.call_callback2("Failing", 7),
+            Err(PluginError::Callback(_))
+        ));
+    }
+    assert!(!manager.is_enabled("Failing"));
+    assert!(matches!(
+        manager.call_callback2("Failing", 7),
+        Err(PluginError::Disabled(_))
+    ));
+    assert!(matches!(
+        manager.call_with_timeout("Failing", 7, timeout),
+        Err(PluginError::Disabled(_))
+    ));
+    assert!(matches!(
+        manager.run_command("Failing", "anything", &[]),
+        Err(PluginError::Disabled(_))
+    ));
+
+    // Failures through `call_with_timeout` count too.
+    assert!(manager.reset_failures("Failing"));
+    for _ in 0..2 {
+        assert!(matches!(
+            manager.call_with_timeout("Failing", 7, timeout),
+            Err(PluginError::Callback(_))
+        ));
+    }
+    assert!(!manager.is_enabled("Failing"));
+}
+
+#[test]
+fn stops_a_callback_once_cancelled() {
+    let (logger, logs) = common::capture_logs();
+    let mut manager = PluginManagerBuilder::new().log_sink(logger).build();
+    manager
+        .load_plugin(&common::library("test_plugin"))
+        .unwrap();
+    let logged = |message: &str| {
+        logs.lock()
+            .unwrap()
+            .iter()
+            .any(|(target, logged)| target == "Probe" && logged == message)
+    };
+
+    // `Probe::callback1` waits five seconds to be cancelled.
+    let start = Instant::now();
+    let manager = &manager;
+    thread::scope(|scope| {
+        let call = scope.spawn(|| manager.run_all_callback1());
+        while !logged("waiting") {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(manager.cancel("Probe"));
+        assert!(call.join().unwrap().is_empty());
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(logged("cancelled"));
+}
+
+#[test]
+fn runs_only_the_plugins_in_a_group() {
+    let mut manager = PluginManagerBuilder::new().build();
+    common::load_examples(&mut manager);
+    // The "math" group is PluginA and PluginB; PluginDouble isn't in a group.
+    assert_eq!(
+        manager.run_group("math", 7),
+        [("PluginA".to_string(), 8), ("PluginB".to_string(), 6)]
+    );
+    assert!(manager.run_group("missing", 7).is_empty());
+}
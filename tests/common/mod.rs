@@ -0,0 +1,76 @@
+// Each test binary uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex, Once};
+
+use core::{HostLogger, LogLevel};
+use plugin_example::PluginManager;
+
+/// The libraries the tests load, built once per test binary.
+const LIBRARIES: &[&str] = &[
+    "plugin_a",
+    "plugin_b",
+    "stale_plugin",
+    "test_plugin",
+    "panic_plugin",
+];
+
+/// The path of the workspace library `stem`, building the test libraries first if this is the
+/// first call. They're built into the same directory as the tests, with the same profile.
+pub fn library(stem: &str) -> PathBuf {
+    static BUILD: Once = Once::new();
+
+    // The test binary lives in `target/<profile>/deps`.
+    let dir = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|deps| deps.parent())
+        .unwrap()
+        .to_path_buf();
+    BUILD.call_once(|| {
+        let mut cargo = Command::new(env!("CARGO"));
+        cargo
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .arg("build")
+            .arg("--quiet");
+        if dir.ends_with("release") {
+            cargo.arg("--release");
+        }
+        for library in LIBRARIES {
+            cargo.args(["-p", library]);
+        }
+        let status = cargo.status().expect("failed to run cargo");
+        assert!(status.success(), "failed to build the test libraries");
+    });
+    dir.join(core::platform_lib_name(stem))
+}
+
+/// Loads `plugin_a` and then `plugin_b` into `manager`.
+pub fn load_examples(manager: &mut PluginManager) {
+    manager.load_plugin(&library("plugin_a")).unwrap();
+    manager.load_plugin(&library("plugin_b")).unwrap();
+}
+
+/// The messages logged through a `CapturingLogger`, as `(target, message)` pairs.
+pub type Logs = Arc<Mutex<Vec<(String, String)>>>;
+
+/// A `HostLogger` that records every message so tests can check what plugins logged.
+pub struct CapturingLogger(Logs);
+
+impl HostLogger for CapturingLogger {
+    fn log(&self, _level: LogLevel, target: &str, message: &str) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((target.to_string(), message.to_string()));
+    }
+}
+
+/// A logger to pass to `log_sink`, and the messages it records.
+pub fn capture_logs() -> (Box<dyn HostLogger>, Logs) {
+    let logs = Logs::default();
+    (Box::new(CapturingLogger(logs.clone())), logs)
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use plugin_example::PluginManagerBuilder;
+
+#[test]
+fn delivers_published_events_to_subscribers() {
+    let (logger, logs) = common::capture_logs();
+    let mut manager = PluginManagerBuilder::new().log_sink(logger).build();
+    common::load_examples(&mut manager);
+
+    // PluginB publishes "ping" from callback2, and PluginA logs every ping it gets.
+    assert_eq!(manager.call_callback2("PluginB", 7).unwrap(), 6);
+    let logs = logs.lock().unwrap();
+    assert!(logs.contains(&("PluginA".to_string(), "got ping: from PluginB".to_string())));
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+use core::PluginError;
+use libloading::Symbol;
+use plugin_example::PluginManagerBuilder;
+
+#[test]
+fn loads_both_example_plugins() {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager.load_plugin(&common::library("plugin_b")).unwrap();
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+    assert_eq!(manager.call_callback2("PluginB", 7).unwrap(), 6);
+}
+
+#[test]
+fn survives_a_plugin_that_panics() {
+    let mut manager = PluginManagerBuilder::new()
+        .with_panic_isolation(true)
+        .build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager
+        .load_plugin(&common::library("panic_plugin"))
+        .unwrap();
+
+    let failures = manager.run_all_callback1();
+    assert_eq!(failures.len(), 1);
+    assert!(matches!(failures[0], ("Panicky", PluginError::Panicked(_))));
+    assert!(matches!(
+        manager.call_callback2("Panicky", 7),
+        Err(PluginError::Panicked(_))
+    ));
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+}
+
+#[test]
+fn refuses_a_library_built_against_another_abi_version() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let path = common::library("stale_plugin");
+    match manager.load_plugin(&path) {
+        Err(PluginError::AbiMismatch { expected, found }) => {
+            assert_eq!(expected, core::ABI_VERSION);
+            assert_eq!(found, Some(core::ABI_VERSION - 1));
+        }
+        result => panic!("expected an ABI mismatch, got {:?}", result),
+    }
+    assert!(manager.is_empty());
+    assert_eq!(manager.failed_paths(), [path]);
+}
+
+#[test]
+fn skips_a_plugin_that_panics_while_loading() {
+    let config = HashMap::from([("panic_on_load".to_string(), "true".to_string())]);
+    let mut manager = PluginManagerBuilder::new()
+        .with_panic_isolation(true)
+        .with_config(Arc::new(config))
+        .build();
+    assert!(matches!(
+        manager.load_plugin(&common::library("panic_plugin")),
+        Err(PluginError::Panicked(_))
+    ));
+    assert!(manager.get("Panicky").is_none());
+
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+}
+
+#[test]
+fn skips_a_library_thats_already_loaded() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let plugin_b = common::library("plugin_b");
+    let report = manager.load_all(&[plugin_b.clone(), plugin_b.clone()]);
+    assert_eq!(report.loaded, ["PluginB"]);
+    assert_eq!(report.skipped, [plugin_b]);
+    assert!(report.failed.is_empty());
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["PluginB"]);
+}
+
+#[test]
+fn loads_a_library_from_bytes() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let bytes = fs::read(common::library("plugin_a")).unwrap();
+    manager.load_from_bytes(&bytes).unwrap();
+    assert_eq!(
+        manager.names().collect::<Vec<_>>(),
+        ["PluginA", "PluginDouble"]
+    );
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+
+    // The library is reopened from the same temporary file, which outlives the reload.
+    let temp = manager.source_path("PluginA").unwrap().to_path_buf();
+    manager.reload("PluginA").unwrap();
+    assert_eq!(manager.source_path("PluginA"), Some(temp.as_path()));
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+
+    manager.unload_all();
+    assert!(!temp.exists());
+}
+
+#[test]
+fn records_libraries_from_bytes_that_fail_to_load() {
+    let mut manager = PluginManagerBuilder::new().build();
+    assert!(matches!(
+        manager.load_from_bytes(b"not a library"),
+        Err(PluginError::LibraryOpen(_))
+    ));
+    assert_eq!(manager.failed_paths().len(), 1);
+    assert!(manager.is_empty());
+}
+
+#[test]
+fn looks_up_other_symbols_through_raw_library() {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager
+        .load_plugin(&common::library("test_plugin"))
+        .unwrap();
+    let library = manager.raw_library("Probe").unwrap();
+    let extra_symbol: Symbol<unsafe extern "C" fn() -> u32> =
+        unsafe { library.get(b"extra_symbol") }.unwrap();
+    assert_eq!(unsafe { extra_symbol() }, 42);
+    assert!(manager.raw_library("Missing").is_none());
+}
+
+#[test]
+fn reports_the_library_each_plugin_came_from() {
+    let mut manager = PluginManagerBuilder::new().build();
+    common::load_examples(&mut manager);
+    let plugin_a = common::library("plugin_a");
+    assert_eq!(manager.source_path("PluginA"), Some(plugin_a.as_path()));
+    assert_eq!(
+        manager.source_path("PluginDouble"),
+        Some(plugin_a.as_path())
+    );
+    assert_eq!(
+        manager.source_path("PluginB"),
+        Some(common::library("plugin_b").as_path())
+    );
+    assert_eq!(manager.source_path("PluginC"), None);
+}
+
+#[test]
+fn leaves_out_plugins_the_registration_hook_vetoes() {
+    let mut manager = PluginManagerBuilder::new()
+        .on_register(|plugin| plugin.metadata().name == "PluginA")
+        .build();
+    let report = manager.load_all(&[common::library("plugin_a"), common::library("plugin_b")]);
+    assert_eq!(report.loaded, ["PluginA"]);
+    assert_eq!(report.vetoed, ["PluginDouble", "PluginB"]);
+    assert!(report.failed.is_empty());
+    assert!(manager.failed_paths().is_empty());
+    assert_eq!(manager.vetoed(), ["PluginDouble", "PluginB"]);
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["PluginA"]);
+    assert!(matches!(
+        manager.call_callback2("PluginB", 7),
+        Err(PluginError::NotFound(_))
+    ));
+}
+
+#[test]
+fn loads_a_directory_in_order_of_file_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let library = |name: &str| {
+        dir.path()
+            .join(format!("{}.{}", name, core::DYLIB_EXTENSION))
+    };
+    fs::copy(common::library("plugin_b"), library("1-b")).unwrap();
+    fs::copy(common::library("plugin_a"), library("2-a")).unwrap();
+    fs::write(dir.path().join("0-notes.txt"), "not a library").unwrap();
+
+    let mut manager = PluginManagerBuilder::new().build();
+    let report = manager.load_dir(dir.path()).unwrap();
+    let paths: Vec<_> = report
+        .timings
+        .iter()
+        .map(|(path, _)| path.clone())
+        .collect();
+    assert_eq!(paths, [library("1-b"), library("2-a")]);
+    assert_eq!(report.loaded, ["PluginB", "PluginA", "PluginDouble"]);
+    assert!(report.failed.is_empty());
+}
+
+#[test]
+fn reports_which_libraries_loaded_and_which_failed() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join(core::platform_lib_name("missing"));
+    let stale = common::library("stale_plugin");
+    let mut manager = PluginManagerBuilder::new().build();
+    let report = manager.load_all(&[
+        common::library("plugin_a"),
+        missing.clone(),
+        stale.clone(),
+        common::library("plugin_b"),
+    ]);
+
+    assert_eq!(report.loaded, ["PluginA", "PluginDouble", "PluginB"]);
+    let failed: Vec<_> = report.failed.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(failed, [missing.clone(), stale.clone()]);
+    assert!(matches!(report.failed[0].1, PluginError::LibraryOpen(_)));
+    assert!(matches!(
+        report.failed[1].1,
+        PluginError::AbiMismatch { .. }
+    ));
+    assert_eq!(report.timings.len(), 4);
+    assert_eq!(manager.failed_paths(), [missing, stale]);
+}
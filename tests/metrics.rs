@@ -0,0 +1,24 @@
+mod common;
+
+use std::sync::Arc;
+
+use plugin_example::metrics::{CALLBACK_INVOCATIONS, PLUGINS_LOADED};
+use plugin_example::{CountingSink, PluginManagerBuilder};
+
+#[test]
+fn counts_every_callback_invocation() {
+    let metrics = Arc::new(CountingSink::new());
+    let mut manager = PluginManagerBuilder::new()
+        .metrics_sink(metrics.clone())
+        .build();
+    common::load_examples(&mut manager);
+    assert_eq!(metrics.snapshot()[PLUGINS_LOADED], 3);
+    assert!(!metrics.snapshot().contains_key(CALLBACK_INVOCATIONS));
+
+    // PluginB doesn't handle `callback1`, so this calls PluginA and PluginDouble.
+    assert!(manager.run_all_callback1().is_empty());
+    manager.call_callback2("PluginA", 7).unwrap();
+    manager.call_callback2("PluginB", 7).unwrap();
+    assert_eq!(manager.map_callback2(7).len(), 3);
+    assert_eq!(metrics.snapshot()[CALLBACK_INVOCATIONS], 2 + 2 + 3);
+}
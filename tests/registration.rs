@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use core::{Plugin, PluginConfig, PluginError, PluginMetadata, PluginRegistrar};
+use plugin_example::PluginManagerBuilder;
+
+/// A plugin registered in-process, without a library, that adds `increment` to its input.
+struct Adder {
+    increment: i32,
+}
+
+impl Plugin for Adder {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Adder",
+            version: "1.0.0",
+            description: None,
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        Ok(i + self.increment)
+    }
+}
+
+fn instance(name: &str, enabled: bool) -> PluginConfig {
+    PluginConfig {
+        enabled,
+        instance_name: Some(name.to_string()),
+    }
+}
+
+#[test]
+fn registers_one_plugin_under_several_instance_names() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    registrar.register_plugin_with_config(Box::new(Adder { increment: 1 }), instance("one", true));
+    registrar.register_plugin_with_config(Box::new(Adder { increment: 2 }), instance("two", true));
+
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["one", "two"]);
+    assert_eq!(manager.call_callback2("one", 7).unwrap(), 8);
+    assert_eq!(manager.call_callback2("two", 7).unwrap(), 9);
+    assert!(matches!(
+        manager.call_callback2("Adder", 7),
+        Err(PluginError::NotFound(_))
+    ));
+}
+
+#[test]
+fn skips_disabled_plugins() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    registrar.register_plugin_with_config(Box::new(Adder { increment: 1 }), instance("on", true));
+    registrar.register_plugin_with_config(Box::new(Adder { increment: 2 }), instance("off", false));
+
+    let enabled: Vec<_> = manager.enabled_plugins().map(|(name, _)| name).collect();
+    assert_eq!(enabled, ["on"]);
+    assert!(!manager.is_enabled("off"));
+    assert!(matches!(
+        manager.call_callback2("off", 7),
+        Err(PluginError::Disabled(_))
+    ));
+}
+
+#[test]
+fn keeps_ids_when_another_plugin_is_unloaded() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    let mut register = |name, increment| {
+        registrar.register_plugin_with_config(Box::new(Adder { increment }), instance(name, true))
+    };
+    let ids = [
+        register("first", 0),
+        register("second", 1),
+        register("third", 2),
+    ];
+
+    assert!(manager.unload("second"));
+    assert!(manager.get_by_id(ids[1]).is_none());
+    assert_eq!(manager.id("first"), Some(ids[0]));
+    assert_eq!(manager.id("third"), Some(ids[2]));
+    assert_eq!(manager.get_by_id(ids[2]).unwrap().callback2(7).unwrap(), 9);
+}
+
+#[test]
+fn calls_both_closures_of_a_closure_plugin() {
+    let called = Arc::new(AtomicBool::new(false));
+    let mut manager = PluginManagerBuilder::new().build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    let flag = called.clone();
+    registrar.register_fn(
+        "Closures",
+        move || flag.store(true, Ordering::SeqCst),
+        |i| i * 3,
+    );
+
+    assert!(manager.run_all_callback1().is_empty());
+    assert!(called.load(Ordering::SeqCst));
+    assert_eq!(manager.call_callback2("Closures", 7).unwrap(), 21);
+}
+
+/// Like `Adder`, but with a version of its own.
+struct Versioned {
+    version: &'static str,
+    increment: i32,
+}
+
+impl Plugin for Versioned {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "Versioned",
+            version: self.version,
+            description: None,
+        }
+    }
+
+    fn callback2(&self, i: i32) -> Result<i32, String> {
+        Ok(i + self.increment)
+    }
+}
+
+#[test]
+fn keeps_the_newest_version_of_a_plugin() {
+    let mut manager = PluginManagerBuilder::new().build();
+    let registrar: &mut dyn PluginRegistrar = &mut manager;
+    for (version, increment) in [("1.2.0", 1), ("1.0.0", 2), ("1.10.0", 3)].iter() {
+        registrar.register_plugin(Box::new(Versioned {
+            version,
+            increment: *increment,
+        }));
+    }
+    assert_eq!(
+        manager.names().collect::<Vec<_>>(),
+        ["Versioned", "Versioned@1.0.0", "Versioned@1.10.0"]
+    );
+    assert_eq!(manager.call_callback2("Versioned@1.0.0", 7).unwrap(), 9);
+
+    assert_eq!(
+        manager.dedup_by_name_keeping_newest(),
+        ["Versioned", "Versioned@1.0.0"]
+    );
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["Versioned"]);
+    assert_eq!(
+        manager.get("Versioned").unwrap().metadata().version,
+        "1.10.0"
+    );
+    assert_eq!(manager.call_callback2("Versioned", 7).unwrap(), 10);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use plugin_example::PluginManagerBuilder;
+
+#[test]
+fn reloads_a_plugin_in_place() {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager.load_plugin(&common::library("plugin_b")).unwrap();
+    let names: Vec<String> = manager.names().map(str::to_string).collect();
+    assert_eq!(names, ["PluginA", "PluginDouble", "PluginB"]);
+    let ids: Vec<_> = names.iter().map(|name| manager.id(name).unwrap()).collect();
+
+    manager.reload("PluginB").unwrap();
+    assert_eq!(manager.names().collect::<Vec<_>>(), names);
+    for (name, id) in names.iter().zip(&ids) {
+        assert_eq!(manager.id(name), Some(*id));
+        assert_eq!(
+            manager.get_by_id(*id).unwrap().metadata().name,
+            name.as_str()
+        );
+    }
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+    assert_eq!(manager.call_callback2("PluginB", 7).unwrap(), 6);
+}
+
+#[test]
+fn keeps_plugin_state_across_reloads() {
+    let (logger, logs) = common::capture_logs();
+    let mut manager = PluginManagerBuilder::new().log_sink(logger).build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager.reload("PluginA").unwrap();
+
+    let loads: Vec<_> = logs
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(target, message)| target == "PluginA" && message.starts_with("loaded "))
+        .map(|(_, message)| message.clone())
+        .collect();
+    assert_eq!(loads, ["loaded 1 times", "loaded 2 times"]);
+}
+
+#[test]
+fn reloads_a_watched_library_once_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(core::platform_lib_name("plugin_a"));
+    fs::copy(common::library("plugin_a"), &path).unwrap();
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&path).unwrap();
+    manager.watch(std::slice::from_ref(&path)).unwrap();
+    assert!(manager.poll_reloads().is_empty());
+
+    // Replace the library the way a build would, rather than rewriting the mapped file.
+    let fresh = dir.path().join("fresh");
+    fs::copy(common::library("plugin_a"), &fresh).unwrap();
+    fs::rename(&fresh, &path).unwrap();
+    let start = Instant::now();
+    let reloaded = loop {
+        let reloaded = manager.poll_reloads();
+        if !reloaded.is_empty() || start.elapsed() > Duration::from_secs(10) {
+            break reloaded;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(reloaded, [fs::canonicalize(&path).unwrap()]);
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+}
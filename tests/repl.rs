@@ -0,0 +1,80 @@
+mod common;
+
+use plugin_example::{repl, PluginManager, PluginManagerBuilder};
+
+fn manager() -> PluginManager {
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&common::library("plugin_a")).unwrap();
+    manager.load_plugin(&common::library("plugin_b")).unwrap();
+    manager
+}
+
+/// Runs `script` through the REPL, returning everything it wrote.
+fn run(manager: &mut PluginManager, script: &str) -> String {
+    let mut output = Vec::new();
+    repl::run(manager, script.as_bytes(), &mut output);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn lists_and_calls_plugins() {
+    let mut manager = manager();
+    let output = run(
+        &mut manager,
+        "list\ncall PluginA 7\ncall PluginB 7\ncall PluginA double 21\n",
+    );
+    assert_eq!(
+        output,
+        "> PluginA (commands: double)\nPluginDouble\nPluginB\n> 8\n> 6\n> 42\n> "
+    );
+}
+
+#[test]
+fn unloads_and_reloads_plugins() {
+    let mut manager = manager();
+    let output = run(
+        &mut manager,
+        "reload PluginB\ncall PluginB 7\nunload PluginDouble\nlist\n",
+    );
+    assert_eq!(output, "> > 6\n> > PluginA (commands: double)\nPluginB\n> ");
+}
+
+#[test]
+fn reports_unknown_plugins_and_commands_without_stopping() {
+    let mut manager = manager();
+    let script = [
+        "call Missing 7",
+        "unload Missing",
+        "reload Missing",
+        "call PluginA triple 1",
+        "frobnicate",
+        "call PluginA 7",
+    ];
+    let results: Vec<String> = script
+        .iter()
+        .map(|line| {
+            let mut output = Vec::new();
+            assert!(repl::run_command(&mut manager, line, &mut output).unwrap());
+            String::from_utf8(output).unwrap()
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            "error: no plugin named `Missing` is registered\n",
+            "error: no plugin named `Missing` is registered\n",
+            "error: no plugin named `Missing` is registered\n",
+            "error: plugin `PluginA` has no command named `triple`\n",
+            "commands: list, call <name> <int>, call <name> <command> <args...>, \
+             unload <name>, reload <name>, quit\n",
+            "8\n",
+        ]
+    );
+}
+
+#[test]
+fn stops_at_quit() {
+    let mut manager = manager();
+    assert_eq!(run(&mut manager, "quit\ncall PluginA 7\n"), "> ");
+    assert!(!repl::run_command(&mut manager, "exit", &mut Vec::new()).unwrap());
+}
@@ -0,0 +1,69 @@
+// Whether a library is still loaded is read from `/proc/self/maps`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use core::{HostLogger, LogLevel};
+use plugin_example::PluginManagerBuilder;
+
+/// Whether the library at `path` is mapped into this process.
+fn is_mapped(path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap();
+    fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .contains(path.to_str().unwrap())
+}
+
+/// Records, each time `Probe` logs that it was dropped, whether its library was still mapped.
+struct DropLogger {
+    library: PathBuf,
+    drops: Arc<Mutex<Vec<bool>>>,
+}
+
+impl HostLogger for DropLogger {
+    fn log(&self, _level: LogLevel, target: &str, message: &str) {
+        if target == "Probe" && message == "dropped" {
+            let mapped = is_mapped(&self.library);
+            self.drops.lock().unwrap().push(mapped);
+        }
+    }
+}
+
+#[test]
+fn drops_plugins_before_closing_their_library() {
+    let path = common::library("test_plugin");
+    let drops = Arc::new(Mutex::new(Vec::new()));
+    let mut manager = PluginManagerBuilder::new()
+        .log_sink(Box::new(DropLogger {
+            library: path.clone(),
+            drops: drops.clone(),
+        }))
+        .build();
+    manager.load_plugin(&path).unwrap();
+    assert!(is_mapped(&path));
+
+    assert!(manager.unload("Probe"));
+    assert_eq!(*drops.lock().unwrap(), [true]);
+    assert!(!is_mapped(&path));
+}
+
+#[test]
+fn keeps_a_library_open_while_any_of_its_plugins_is_loaded() {
+    // A copy of its own, so no other test has the library open.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(core::platform_lib_name("plugin_a"));
+    fs::copy(common::library("plugin_a"), &path).unwrap();
+    let mut manager = PluginManagerBuilder::new().build();
+    manager.load_plugin(&path).unwrap();
+
+    assert!(manager.unload("PluginDouble"));
+    assert!(is_mapped(&path));
+    assert_eq!(manager.call_callback2("PluginA", 7).unwrap(), 8);
+
+    assert!(manager.unload("PluginA"));
+    assert!(!is_mapped(&path));
+}